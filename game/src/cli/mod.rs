@@ -1,4 +1,6 @@
-use clap::{arg, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -12,13 +14,120 @@ pub struct Cli {
 pub enum Commands {
     #[command(about = "Start the game server")]
     Serve {
-        /// The type of the game: "Zero", "Finite" or "Infinite"
+        /// The type of the game: "zero", "finite", "infinite" or "second-order"
         #[arg(short, long)]
         mode: String,
+        /// Path to a TOML or JSON file with the game config, the default config is used if omitted
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+        /// Seed of every session, a random seed is drawn for each session if omitted
+        #[arg(short, long)]
+        seed: Option<u64>,
+        /// Directory where the transcript of every session is written as JSON lines
+        #[arg(short, long)]
+        record: Option<PathBuf>,
+        /// Path to a YAML or JSON script of the whole turn to play, every turn, instead of random ones
+        #[arg(long)]
+        scenario: Option<PathBuf>,
     },
      #[command(about = "Start the game client")]
     Client {
          #[arg(short, long)]
          port: usize,
+         /// Seed of the session, chosen by the server if omitted
+         #[arg(short, long)]
+         seed: Option<u64>,
+         #[command(flatten)]
+         player: PlayerArgs,
+         /// Number of turns the bot plays
+         #[arg(short, long, default_value_t = 1)]
+         games: usize,
     },
+    #[command(about = "Write episodes of the game with the right answers, to be played without a server")]
+    Export {
+        /// The type of the game: "zero", "finite", "infinite" or "second-order"
+        #[arg(short, long)]
+        mode: String,
+        /// Path to a TOML or JSON file with the game config, the default config is used if omitted
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+        /// Seed the seeds of the episodes are drawn from
+        #[arg(short, long, default_value_t = 0)]
+        seed: u64,
+        /// Number of episodes
+        #[arg(short, long)]
+        n: usize,
+        /// JSON lines file the episodes are written to, one episode per line
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    #[command(about = "Play turns of the game with a bot, an external program or a chat model and write the distribution of the scores")]
+    Bench {
+        /// The type of the game: "zero", "finite", "infinite" or "second-order", the mode of the server if a port is given
+        #[arg(short, long, required_unless_present = "port")]
+        mode: Option<String>,
+        /// Port of the server to play on, the game is run in-process if omitted
+        #[arg(short, long)]
+        port: Option<usize>,
+        /// Path to a TOML or JSON file with the config of the game run in-process, the default config is used if omitted
+        #[arg(short, long, conflicts_with = "port")]
+        config: Option<PathBuf>,
+        /// Seed of the session, chosen by the server if a port is given, else at random, if omitted
+        #[arg(short, long)]
+        seed: Option<u64>,
+        /// Number of turns to play
+        #[arg(short, long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        turns: usize,
+        #[command(flatten)]
+        player: PlayerArgs,
+        /// Name of the player in the results, the bot, the program or the chat model by default
+        #[arg(long)]
+        name: Option<String>,
+        /// Directory the results are written to
+        #[arg(short, long, default_value = "results")]
+        output: PathBuf,
+    },
+    #[command(about = "Play a recorded session again and check that the game sends the same lines")]
+    Replay {
+        /// Transcript of the session, as written by `serve --record`
+        file: PathBuf,
+        /// Only report the scores of the replayed session, without failing on differences
+        #[arg(long)]
+        rescore: bool,
+    },
+}
+
+/// PlayerArgs choose who plays instead of the keyboard.
+#[derive(Args)]
+pub struct PlayerArgs {
+    /// Play with a baseline bot: "oracle", "reality", "initial", "last-shown" or "random"
+    #[arg(short, long, conflicts_with_all = ["exec", "chat"])]
+    pub bot: Option<String>,
+    /// Play with an external program, run with the shell: it reads every line of the game as a JSON line on its
+    /// standard input and writes its answer to every line requiring one on its standard output
+    #[arg(long, conflicts_with = "chat")]
+    pub exec: Option<String>,
+    /// Seconds the program or the chat model may take to answer a line: the program is started again and the line
+    /// answered with nothing, the chat model fails
+    #[arg(long, default_value_t = 60)]
+    pub timeout: u64,
+    /// Number of times the program may be started again after crashing or not answering in time
+    #[arg(long, default_value_t = 3)]
+    pub restarts: usize,
+    /// Play with a chat model, served by the OpenAI-compatible API at this base URL, such as http://localhost:11434/v1;
+    /// the key of the API is read from OPENAI_API_KEY, if set
+    #[arg(long, requires = "model")]
+    pub chat: Option<String>,
+    /// Name of the chat model
+    #[arg(long)]
+    pub model: Option<String>,
+    /// Temperature of the chat model
+    #[arg(long, default_value_t = 0.0)]
+    pub temperature: f64,
+    /// Maximum number of tokens of every answer of the chat model
+    #[arg(long, default_value_t = 20)]
+    pub max_tokens: usize,
+    /// Instructions given to the chat model before the game, none if omitted
+    #[arg(long)]
+    pub system_prompt: Option<String>,
 }
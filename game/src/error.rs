@@ -0,0 +1,60 @@
+use std::fmt::Display;
+use std::io;
+
+use crate::logic::config::ConfigError;
+
+/// GameError is an enum that holds the errors that can end a session of the game.
+#[derive(Debug)]
+pub enum GameError {
+    /// The other side closed the connection
+    Disconnected,
+    /// The connection failed
+    Io(io::Error),
+    /// A line received from the other side is not a valid message
+    MalformedInput(String),
+    /// The other side does not speak the same protocol
+    Protocol(String),
+    /// The config of the game cannot be used
+    Config(ConfigError),
+    /// The program playing the game failed
+    Player(String),
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameError::Disconnected => write!(f, "Connection closed"),
+            GameError::Io(e) => write!(f, "Connection error: {}", e),
+            GameError::MalformedInput(e) => write!(f, "Malformed input: {}", e),
+            GameError::Protocol(e) => write!(f, "Protocol error: {}", e),
+            GameError::Config(e) => write!(f, "{}", e),
+            GameError::Player(e) => write!(f, "Player error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<io::Error> for GameError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => GameError::Disconnected,
+            _ => GameError::Io(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for GameError {
+    fn from(e: serde_json::Error) -> Self {
+        GameError::MalformedInput(e.to_string())
+    }
+}
+
+impl From<ConfigError> for GameError {
+    fn from(e: ConfigError) -> Self {
+        GameError::Config(e)
+    }
+}
@@ -1,3 +1,4 @@
+pub mod error;
 pub mod utils;
 pub mod logic;
 pub mod cli;
\ No newline at end of file
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use super::message::ProbeAnswer;
+
+/// CARDINALS are the number words read as numbers, by value
+const CARDINALS: [&str; 21] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen", "twenty",
+];
+
+/// ORDINALS are the ordinal words read as numbers, by value, as the game names positions: the 0th is the first one
+const ORDINALS: [&str; 21] = [
+    "zeroth", "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth", "tenth",
+    "eleventh", "twelfth", "thirteenth", "fourteenth", "fifteenth", "sixteenth", "seventeenth", "eighteenth",
+    "nineteenth", "twentieth",
+];
+
+/// KEYWORDS are the words introducing the answer among other numbers, as in "User 3 goes to position 2"
+const KEYWORDS: [&str; 4] = ["position", "answer", "prediction", "predict"];
+
+/// FILLERS are the words allowed between a keyword and the number it introduces, as in "the answer is the 2nd"
+const FILLERS: [&str; 6] = ["is", "the", "at", "to", "in", "number"];
+
+/// AnswerPolicy is how the answers of the player are read.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerPolicy {
+    /// The answer must be a bare number, or yes or no
+    #[default]
+    Strict,
+    /// The answer is looked for in free text: numerals, ordinals ("3rd"), number words ("two", "third") or JSON.
+    /// Among several numbers, the one introduced by "position" or "answer" is read; if there is none, or several,
+    /// the answer cannot be read
+    Lenient,
+}
+
+impl AnswerPolicy {
+    /// read_number reads a number, such as a position, from the answer
+    pub fn read_number(&self, answer: &str) -> Option<usize> {
+        let answer = answer.trim();
+        match self {
+            AnswerPolicy::Strict => answer.parse().ok(),
+            AnswerPolicy::Lenient => {
+                let tokens = tokenize(answer);
+                let numbers: Vec<(usize, usize)> = tokens.iter().enumerate()
+                    // "one" is no number after an ordinal, as in "the 3rd one"
+                    .filter(|&(i, token)| !(token == "one" && i > 0 && is_ordinal(&tokens[i - 1])))
+                    .filter_map(|(i, token)| token_number(token).map(|number| (i, number)))
+                    .collect();
+                unique(numbers.iter().map(|&(_, number)| number)).or_else(|| {
+                    unique(numbers.iter().filter(|&&(i, _)| is_keyed(&tokens, i)).map(|&(_, number)| number))
+                })
+            }
+        }
+    }
+
+    /// read_yes_no reads yes or no from the answer
+    pub fn read_yes_no(&self, answer: &str) -> Option<bool> {
+        let answer = answer.trim().to_lowercase();
+        match self {
+            AnswerPolicy::Strict => token_yes_no(&answer),
+            AnswerPolicy::Lenient => unique(tokenize(&answer).iter().filter_map(|token| token_yes_no(token))),
+        }
+    }
+
+    /// read reads an answer of the same kind as the expected one
+    pub fn read(&self, answer: &str, expected: &ProbeAnswer) -> Option<ProbeAnswer> {
+        match expected {
+            ProbeAnswer::Number(_) => self.read_number(answer).map(ProbeAnswer::Number),
+            ProbeAnswer::YesNo(_) => self.read_yes_no(answer).map(ProbeAnswer::YesNo),
+        }
+    }
+}
+
+/// tokenize splits the answer into lowercase words and numbers
+fn tokenize(answer: &str) -> Vec<String> {
+    answer
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+/// token_number reads a numeral, an ordinal or a number word
+fn token_number(token: &str) -> Option<usize> {
+    if let Ok(number) = token.parse() {
+        return Some(number);
+    }
+    let digits = token.trim_end_matches(|c: char| c.is_alphabetic());
+    if !digits.is_empty() && ["st", "nd", "rd", "th"].contains(&&token[digits.len()..]) {
+        return digits.parse().ok();
+    }
+    CARDINALS.iter().position(|word| *word == token).or_else(|| ORDINALS.iter().position(|word| *word == token))
+}
+
+/// is_ordinal tells whether the token is an ordinal, such as "3rd" or "third"
+fn is_ordinal(token: &str) -> bool {
+    ORDINALS.contains(&token) || (token.starts_with(|c: char| c.is_ascii_digit()) && token.parse::<usize>().is_err() && token_number(token).is_some())
+}
+
+fn token_yes_no(token: &str) -> Option<bool> {
+    match token {
+        "yes" | "y" | "true" => Some(true),
+        "no" | "n" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// is_keyed tells whether the token is introduced by a keyword
+fn is_keyed(tokens: &[String], i: usize) -> bool {
+    tokens[..i].iter().rev()
+        .find(|token| !FILLERS.contains(&token.as_str()))
+        .is_some_and(|token| KEYWORDS.contains(&token.as_str()))
+}
+
+/// unique is the only distinct value, none if there is none or several
+fn unique<T: PartialEq>(mut values: impl Iterator<Item = T>) -> Option<T> {
+    let first = values.next()?;
+    values.all(|value| value == first).then_some(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strict() {
+        assert_eq!(AnswerPolicy::Strict.read_number(" 2\n"), Some(2));
+        assert_eq!(AnswerPolicy::Strict.read_number("position 2"), None);
+        assert_eq!(AnswerPolicy::Strict.read_number("two"), None);
+        assert_eq!(AnswerPolicy::Strict.read_yes_no("Yes"), Some(true));
+        assert_eq!(AnswerPolicy::Strict.read_yes_no("No."), None);
+    }
+
+    #[test]
+    fn test_lenient_numbers() {
+        let read = |answer| AnswerPolicy::Lenient.read_number(answer);
+        assert_eq!(read("2"), Some(2));
+        assert_eq!(read("position 2"), Some(2));
+        assert_eq!(read("The user will go to the 3rd position."), Some(3));
+        assert_eq!(read("2nd position"), Some(2));
+        assert_eq!(read("the 0th"), Some(0));
+        assert_eq!(read("Two"), Some(2));
+        assert_eq!(read("the third one"), Some(3));
+        assert_eq!(read("the 1st one"), Some(1));
+        assert_eq!(read("third"), Some(3));
+        assert_eq!(read(r#"{"position": 4}"#), Some(4));
+        assert_eq!(read(r#"{"user": 1, "answer": "2"}"#), Some(2));
+        assert_eq!(read("User 3 goes to position 1, as 1 is where User 3 saw it"), Some(1));
+        assert_eq!(read("position 1 or position 2"), None);
+        assert_eq!(read("User 3 will go to position 1"), Some(1));
+        assert_eq!(read("The answer is the 2nd"), Some(2));
+        assert_eq!(read("2 or 3"), None);
+        assert_eq!(read("I don't know"), None);
+    }
+
+    #[test]
+    fn test_lenient_yes_no() {
+        let read = |answer| AnswerPolicy::Lenient.read_yes_no(answer);
+        assert_eq!(read("No."), Some(false));
+        assert_eq!(read("Yes, User 2 saw the last state."), Some(true));
+        assert_eq!(read(r#"{"answer": true}"#), Some(true));
+        assert_eq!(read("yes or no"), None);
+        assert_eq!(read("maybe"), None);
+        assert_eq!(AnswerPolicy::Lenient.read("the 1st", &ProbeAnswer::Number(0)), Some(ProbeAnswer::Number(1)));
+    }
+}
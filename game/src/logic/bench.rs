@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
+use crate::utils::player::Player;
+use crate::utils::tcp::{connect, read_until_separator, write_to_stream, Data};
+
+use super::config::GameConfig;
+use super::engine::GameMode;
+use super::message::{Message, CAPABILITY_MESSAGES};
+use super::session::Session;
+
+/// Table is where the turns of a bench are played: a server, or a session of the game run in-process.
+pub enum Table {
+    Remote(TcpStream),
+    Local { session: Box<Session>, pending: VecDeque<Data> },
+}
+
+impl Table {
+    /// local starts a session of the game in-process, sending the structured messages
+    pub fn local(mode: GameMode, config: GameConfig, seed: u64) -> Table {
+        let mut session = Box::new(Session::new(mode, config, seed, &[CAPABILITY_MESSAGES.to_string()]));
+        let pending = session.advance(None).into();
+        Table::Local { session, pending }
+    }
+
+    /// receive returns the next line sent by the game, none once the session is over
+    fn receive(&mut self) -> Result<Option<Data>, GameError> {
+        match self {
+            Table::Remote(stream) => match read_until_separator(stream) {
+                Ok(buffer) => Ok(Some(Data::from_json(String::from_utf8_lossy(&buffer).trim())?)),
+                Err(GameError::Disconnected) => Ok(None),
+                Err(e) => Err(e),
+            },
+            Table::Local { session, pending } => match pending.pop_front() {
+                Some(data) => Ok(Some(data)),
+                None if session.is_over() => Ok(None),
+                None => Err(GameError::Protocol("the game waits for an answer it did not ask for".to_string())),
+            },
+        }
+    }
+
+    /// send sends the answer of the player to the game
+    fn send(&mut self, answer: String) -> Result<(), GameError> {
+        let data = Data::new(false, answer);
+        match self {
+            Table::Remote(stream) => write_to_stream(stream, data),
+            Table::Local { session, pending } => {
+                pending.extend(session.advance(Some(data.to_json().as_bytes())));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// play plays `turns` turns on the table with the player and returns the score of every turn, fewer if the game
+/// ends the session first. The question to play again is answered here, it is not shown to the player.
+pub fn play(table: &mut Table, player: &mut dyn Player, turns: usize) -> Result<Vec<usize>, GameError> {
+    let mut scores = vec![];
+    while let Some(data) = table.receive()? {
+        match data.message() {
+            Some(Message::PlayAgain) => {
+                let answer = if scores.len() < turns { "Y" } else { "n" };
+                table.send(answer.to_string())?;
+                continue;
+            }
+            Some(Message::GameOver { score, .. }) => scores.push(*score),
+            _ => {}
+        }
+        if let Some(answer) = player.play(&data)? {
+            table.send(answer)?;
+        }
+    }
+    Ok(scores)
+}
+
+/// Summary is the distribution of the scores of a bench.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub turns: usize,
+    pub mean: f64,
+    /// standard deviation of the sample, 0 with a single turn
+    pub std: f64,
+    /// bounds of the 95% confidence interval of the mean, with the normal approximation
+    pub ci95_low: f64,
+    pub ci95_high: f64,
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Summary {
+    pub fn new(scores: &[usize]) -> Summary {
+        let n = scores.len() as f64;
+        let mean = scores.iter().sum::<usize>() as f64 / n.max(1.0);
+        let std = if scores.len() > 1 {
+            (scores.iter().map(|&score| (score as f64 - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let margin = 1.96 * std / n.max(1.0).sqrt();
+        Summary {
+            turns: scores.len(),
+            mean,
+            std,
+            ci95_low: mean - margin,
+            ci95_high: mean + margin,
+            min: scores.iter().copied().min().unwrap_or(0),
+            max: scores.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+/// BenchReport is the result of a bench: the score of every turn and their summary.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub mode: GameMode,
+    /// name of the player: the bot, the command or the model
+    pub model: String,
+    /// seed of the session the turns were played in
+    pub seed: u64,
+    pub scores: Vec<usize>,
+    pub summary: Summary,
+}
+
+impl BenchReport {
+    pub fn new(mode: GameMode, model: String, seed: u64, scores: Vec<usize>) -> BenchReport {
+        let summary = Summary::new(&scores);
+        BenchReport { mode, model, seed, scores, summary }
+    }
+
+    /// write writes the report to `{dir}/{mode}_{model}_{turns}.json`, and the score of every turn to the `.csv`
+    /// next to it, as the research harness names its results. It returns the path of the JSON file.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, GameError> {
+        std::fs::create_dir_all(dir)?;
+        let stem = format!("{}_{}_{}", self.mode.name(), self.model.replace('/', "_"), self.scores.len());
+        let json = dir.join(format!("{}.json", stem));
+        std::fs::write(&json, serde_json::to_string_pretty(self)?)?;
+        let mut csv = std::fs::File::create(dir.join(format!("{}.csv", stem)))?;
+        writeln!(csv, "turn,score")?;
+        for (turn, score) in self.scores.iter().enumerate() {
+            writeln!(csv, "{},{}", turn, score)?;
+        }
+        Ok(json)
+    }
+}
+
+/// bench plays `turns` turns with the player, on the server listening on `port` if given, else in-process. The
+/// session is seeded with `seed` if given, else by the server, or at random in-process.
+pub fn bench(
+    port: Option<usize>,
+    mode: Option<GameMode>,
+    config: GameConfig,
+    seed: Option<u64>,
+    player: &mut dyn Player,
+    model: String,
+    turns: usize,
+) -> Result<BenchReport, GameError> {
+    let (mut table, mode, seed) = match port {
+        Some(port) => {
+            let (stream, welcome) = connect(port, seed, format!("game-bench/{}", env!("CARGO_PKG_VERSION")))?;
+            (Table::Remote(stream), welcome.mode, welcome.seed)
+        }
+        None => {
+            let mode = mode.ok_or_else(|| GameError::Protocol("the mode of a game played in-process is required".to_string()))?;
+            let seed = seed.unwrap_or_else(rand::random);
+            (Table::local(mode, config, seed), mode, seed)
+        }
+    };
+    let scores = play(&mut table, player, turns)?;
+    Ok(BenchReport::new(mode, model, seed, scores))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::bot::{Bot, BotKind};
+    use crate::utils::player::CommandPlayer;
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::new(&[100, 60, 80, 80]);
+        assert_eq!(summary.turns, 4);
+        assert_eq!(summary.mean, 80.0);
+        assert!((summary.std - (800.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert!((summary.ci95_high - summary.mean - 1.96 * summary.std / 2.0).abs() < 1e-9);
+        assert_eq!((summary.min, summary.max), (60, 100));
+        assert_eq!(Summary::new(&[40]).std, 0.0);
+    }
+
+    #[test]
+    fn test_local_bench() {
+        let mut bot = Bot::new(BotKind::Oracle, 0);
+        let report = bench(None, Some(GameMode::Infinite), GameConfig::default(), Some(7), &mut bot, "oracle".to_string(), 5).unwrap();
+        assert_eq!(report.scores, vec![100; 5]);
+        assert_eq!(report.summary.ci95_low, 100.0);
+
+        let dir = std::env::temp_dir().join(format!("game-bench-{}", std::process::id()));
+        let json = report.write(&dir).unwrap();
+        assert_eq!(json, dir.join("infinite_oracle_5.json"));
+        let written: BenchReport = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(written, report);
+        assert_eq!(std::fs::read_to_string(dir.join("infinite_oracle_5.csv")).unwrap().lines().count(), 6);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_remote_bench_keeps_the_seed_of_the_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port() as usize;
+        let handler = crate::logic::session::start(GameMode::Zero, GameConfig::default(), Some(42), None);
+        std::thread::spawn(move || handler(listener.accept().unwrap().0).ok());
+        let mut bot = Bot::new(BotKind::Oracle, 0);
+        let report = bench(Some(port), None, GameConfig::default(), None, &mut bot, "oracle".to_string(), 2).unwrap();
+        assert_eq!(report.seed, 42);
+        assert_eq!(report.scores, vec![100; 2]);
+    }
+
+    #[test]
+    fn test_command_bench() {
+        // answers the first position to every prompt
+        let command = r#"while read line; do case "$line" in *'"require_input":true'*) echo 0;; esac; done"#;
+        let mut player = CommandPlayer::spawn(command, None, 0).unwrap();
+        let report = bench(None, Some(GameMode::Zero), GameConfig::default(), Some(7), &mut player, "zero".to_string(), 3).unwrap();
+        assert_eq!(report.scores.len(), 3);
+    }
+}
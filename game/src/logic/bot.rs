@@ -0,0 +1,274 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::locker::Locker;
+use super::message::{Message, ProbeAnswer, ProbeKind};
+use super::user::{believed_item_idx, UserCollection};
+
+/// BotKind is the strategy of a reference player.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BotKind {
+    /// Answers the position the user believes, tracking what every user saw from the messages of the game
+    Oracle,
+    /// Answers the position where the item actually is
+    Reality,
+    /// Answers the position where the item was when the turn began
+    Initial,
+    /// Answers the position where the item was in the last snapshot shown by the monitor, whoever looked at it
+    LastShown,
+    /// Answers a position drawn uniformly
+    Random,
+}
+
+impl BotKind {
+    pub const ALL: [BotKind; 5] = [BotKind::Oracle, BotKind::Reality, BotKind::Initial, BotKind::LastShown, BotKind::Random];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotKind::Oracle => "oracle",
+            BotKind::Reality => "reality",
+            BotKind::Initial => "initial",
+            BotKind::LastShown => "last-shown",
+            BotKind::Random => "random",
+        }
+    }
+}
+
+impl std::str::FromStr for BotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BotKind::ALL.into_iter().find(|kind| kind.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = BotKind::ALL.iter().map(|kind| kind.name()).collect();
+            format!("invalid bot {}, choose among {}", s, names.join(", "))
+        })
+    }
+}
+
+/// Bot is a reference player: it only knows the structured messages of the game, as any client.
+///
+/// Every bot keeps track of the lockers and of what each user saw, the oracle answers from it. The other bots answer
+/// probes about positions with their own guess, belief recency probes with 0 and true belief probes with yes, but
+/// the random bot, which answers them at random too. An unannounced faulty monitor fools the oracle as well.
+pub struct Bot {
+    kind: BotKind,
+    rng: StdRng,
+    /// the snapshots of each locker since the beginning of the turn, indexed by the id of the locker
+    snapshots: Vec<Vec<Locker>>,
+    users: UserCollection,
+    /// the snapshot last shown by the monitor of each locker
+    last_shown: Vec<usize>,
+    /// what the user observing right now saw of each locker: the user, the locker, the snapshot and the positions
+    sights: Vec<(usize, usize, usize, Option<Vec<usize>>)>,
+}
+
+impl Bot {
+    pub fn new(kind: BotKind, seed: u64) -> Bot {
+        Bot {
+            kind,
+            rng: StdRng::seed_from_u64(seed),
+            snapshots: vec![],
+            users: UserCollection { users: vec![] },
+            last_shown: vec![],
+            sights: vec![],
+        }
+    }
+
+    pub fn kind(&self) -> BotKind {
+        self.kind
+    }
+
+    /// observe updates what the bot knows with a message of the game
+    pub fn observe(&mut self, message: &Message) {
+        if !matches!(message, Message::Observation { .. } | Message::DisplayFault { .. } | Message::Witness { .. }) {
+            self.sights.clear();
+        }
+        match message {
+            Message::Intro { lockers, .. } => {
+                let user_n = lockers.iter().flatten().flatten().count();
+                let mut user_lockers = vec![0; user_n];
+                for (locker_id, locker) in lockers.iter().enumerate() {
+                    for &user_id in locker.iter().flatten() {
+                        user_lockers[user_id] = locker_id;
+                    }
+                }
+                let locker_sizes: Vec<usize> = lockers.iter().map(|locker| locker.len()).collect();
+                self.users = UserCollection::with_lockers(&user_lockers, &locker_sizes);
+                self.snapshots = lockers.iter().map(|layout| vec![Locker::from_layout(layout)]).collect();
+                self.last_shown = vec![0; lockers.len()];
+            }
+            Message::Shuffle { locker_id, locker } | Message::LockerState { locker_id, locker } => {
+                self.snapshots[*locker_id].push(Locker::from_layout(locker));
+            }
+            Message::Observation { user_id, locker_id, snapshots_ago, positions } => {
+                let snapshot_idx = self.snapshots[*locker_id].len() - 1 - snapshots_ago;
+                self.look(*user_id, *locker_id, snapshot_idx, positions.as_deref());
+                self.sights.push((*user_id, *locker_id, snapshot_idx, positions.clone()));
+            }
+            Message::DisplayFault { user_id, locker_id, snapshots_ago } => {
+                // the user saw an older snapshot than the one told by their latest observation of the locker
+                let snapshot_idx = self.snapshots[*locker_id].len() - 1 - snapshots_ago;
+                if let Some(sight) = self.sights.iter_mut().rev().find(|sight| sight.0 == *user_id && sight.1 == *locker_id) {
+                    sight.2 = snapshot_idx;
+                    let positions = sight.3.clone();
+                    self.look(*user_id, *locker_id, snapshot_idx, positions.as_deref());
+                }
+            }
+            Message::Witness { witness_id, user_id } => {
+                let locker_id = self.users.get_by_id(*user_id).unwrap().locker_id;
+                if let Some((_, _, snapshot_idx, positions)) = self.sights.iter().rev().find(|sight| sight.0 == *user_id && sight.1 == locker_id) {
+                    self.users.get_mut_by_id(*witness_id).unwrap().witness(*user_id, *snapshot_idx, positions.as_deref());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// look updates the beliefs of the user with what they saw of a locker, as the game does
+    fn look(&mut self, user_id: usize, locker_id: usize, snapshot_idx: usize, positions: Option<&[usize]>) {
+        let user = self.users.get_mut_by_id(user_id).unwrap();
+        user.observe(locker_id, snapshot_idx, positions);
+        user.others_inmind_position_state_idx[user_id] = user.inmind_position_state_idx[user.locker_id].clone();
+        self.last_shown[locker_id] = snapshot_idx;
+    }
+
+    /// answer answers a prediction or a probe, an empty answer for any other message
+    pub fn answer(&mut self, request: &Message) -> String {
+        match request {
+            Message::PredictionRequest { user_id, locker_id, n_positions, believer_id } => match self.kind {
+                BotKind::Oracle => {
+                    let believer = self.users.get_by_id(believer_id.unwrap_or(*user_id)).unwrap();
+                    let position_state_idx = match believer_id {
+                        Some(_) => &believer.others_inmind_position_state_idx[*user_id],
+                        None => &believer.inmind_position_state_idx[*locker_id],
+                    };
+                    believed_item_idx(&self.snapshots[*locker_id], position_state_idx, *user_id).to_string()
+                }
+                BotKind::Random => self.rng.gen_range(0..*n_positions).to_string(),
+                _ => self.guess(*user_id).to_string(),
+            },
+            Message::ProbeRequest { kind, user_id } => self.probe_answer(*kind, *user_id).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// guess is the position of the item of the user according to the strategy of the bot, but the oracle and the
+    /// random bot
+    fn guess(&self, user_id: usize) -> usize {
+        let snapshots = &self.snapshots[self.users.get_by_id(user_id).unwrap().locker_id];
+        let snapshot = match self.kind {
+            BotKind::Initial => &snapshots[0],
+            BotKind::LastShown => &snapshots[self.last_shown[self.users.get_by_id(user_id).unwrap().locker_id]],
+            _ => snapshots.last().unwrap(),
+        };
+        snapshot.get_item_idx_by_belongs(user_id)
+    }
+
+    fn probe_answer(&mut self, kind: ProbeKind, user_id: usize) -> ProbeAnswer {
+        let user = self.users.get_by_id(user_id).unwrap();
+        let snapshots = &self.snapshots[user.locker_id];
+        match (self.kind, kind) {
+            (BotKind::Oracle, ProbeKind::Reality | ProbeKind::Control) => ProbeAnswer::Number(snapshots.last().unwrap().get_item_idx_by_belongs(user_id)),
+            (BotKind::Oracle, ProbeKind::Memory) => ProbeAnswer::Number(snapshots[0].get_item_idx_by_belongs(user_id)),
+            (BotKind::Oracle, ProbeKind::BeliefRecency) => ProbeAnswer::Number(snapshots.len() - 1 - user.inmind_own_locker_state_idx()),
+            (BotKind::Oracle, ProbeKind::TrueBelief) => {
+                let inmind_item_idx = believed_item_idx(snapshots, &user.inmind_position_state_idx[user.locker_id], user_id);
+                ProbeAnswer::YesNo(inmind_item_idx == snapshots.last().unwrap().get_item_idx_by_belongs(user_id))
+            }
+            (BotKind::Random, ProbeKind::BeliefRecency) => ProbeAnswer::Number(self.rng.gen_range(0..snapshots.len())),
+            (BotKind::Random, ProbeKind::TrueBelief) => ProbeAnswer::YesNo(self.rng.gen_bool(0.5)),
+            (BotKind::Random, _) => ProbeAnswer::Number(self.rng.gen_range(0..snapshots[0].items.len())),
+            (_, ProbeKind::BeliefRecency) => ProbeAnswer::Number(0),
+            (_, ProbeKind::TrueBelief) => ProbeAnswer::YesNo(true),
+            _ => ProbeAnswer::Number(self.guess(user_id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::config::{GameConfig, PartialObservation};
+    use crate::logic::engine::{Action, Game, GameMode};
+
+    /// play plays a turn of the game with the bot and returns the score and the correct probes out of the probes
+    fn play(game: &mut Game, bot: &mut Bot) -> (usize, usize, usize) {
+        let mut answer = None;
+        loop {
+            for event in game.step(answer.take()) {
+                bot.observe(&event.message);
+                match event.message {
+                    Message::GameOver { score, probes, .. } => {
+                        return (score, probes.iter().map(|probe| probe.correct).sum(), probes.iter().map(|probe| probe.asked).sum());
+                    }
+                    request if request.require_input() => answer = Some(Action::Answer(bot.answer(&request))),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_oracle_is_always_right() {
+        let configs = [
+            GameConfig { probe_probability: 0.5, control_probability: 0.5, ..Default::default() },
+            GameConfig { user_n: 6, locker_n: 2, rooms: vec![vec![0], vec![1]], partial_observation: Some(PartialObservation::Random(2)), ..Default::default() },
+            GameConfig { partial_observation: Some(PartialObservation::OwnItem), display_fault_probability: 0.5, ..Default::default() },
+        ];
+        for config in configs {
+            for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+                for seed in 0..5 {
+                    let (score, probes_correct, probes_asked) = play(&mut Game::new(mode, config.clone(), seed), &mut Bot::new(BotKind::Oracle, seed));
+                    assert_eq!(score, 100);
+                    assert_eq!(probes_correct, probes_asked);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_oracle_follows_the_latest_observation() {
+        let config = GameConfig { partial_observation: Some(PartialObservation::OwnItem), display_fault_probability: 0.7, ..Default::default() };
+        let mut repeated = false;
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+            for seed in 0..10 {
+                let mut game = Game::new(mode, config.clone(), seed);
+                let mut bot = Bot::new(BotKind::Oracle, seed);
+                let mut answer = None;
+                let mut observations: Vec<(usize, usize)> = vec![];
+                'turn: loop {
+                    for event in game.step(answer.take()) {
+                        bot.observe(&event.message);
+                        match event.message {
+                            Message::Observation { user_id, locker_id, .. } => observations.push((user_id, locker_id)),
+                            // the fault is about the latest of several observations of the locker by the user
+                            Message::DisplayFault { user_id, locker_id, .. } => {
+                                repeated |= observations.iter().filter(|&&sight| sight == (user_id, locker_id)).count() > 1;
+                            }
+                            Message::Witness { .. } => {}
+                            Message::Annotation(record) => assert_eq!(record.predicted_idx, Some(record.believed_idx)),
+                            Message::GameOver { .. } => break 'turn,
+                            request if request.require_input() => answer = Some(Action::Answer(bot.answer(&request))),
+                            _ => observations.clear(),
+                        }
+                    }
+                }
+            }
+        }
+        assert!(repeated);
+    }
+
+    #[test]
+    fn test_baselines_play() {
+        for kind in BotKind::ALL {
+            assert_eq!(kind.name().parse(), Ok(kind));
+            let mut bot = Bot::new(kind, 1);
+            let (score, ..) = play(&mut Game::new(GameMode::Finite, GameConfig::default(), 1), &mut bot);
+            assert!(score <= 100);
+        }
+        // the reality bot is right as long as nobody holds a false belief
+        let config = GameConfig { shuffle_probability: 0.0, ..Default::default() };
+        let (score, ..) = play(&mut Game::new(GameMode::Zero, config, 1), &mut Bot::new(BotKind::Reality, 1));
+        assert_eq!(score, 100);
+    }
+}
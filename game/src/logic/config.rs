@@ -0,0 +1,310 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::answer::AnswerPolicy;
+use super::message::ProbeKind;
+use super::scenario::{ScenarioSpec, Script};
+
+/// GameConfig is a struct that holds the settings of the game.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    /// number of users, each one storing one item in the locker
+    pub user_n: usize,
+    /// number of lockers, the users storing their items in the lockers in turn
+    pub locker_n: usize,
+    /// probability that a locker malfunctions and shuffles the items in the Shuffling scene
+    pub shuffle_probability: f64,
+    /// malfunctions of the lockers, the first profile applies to locker 0 and so on, other lockers follow the
+    /// probability above
+    pub locker_profiles: Vec<LockerProfile>,
+    /// lockers shown by each observation room, users entering a random one; a single room shows every locker if empty
+    pub rooms: Vec<Vec<usize>>,
+    /// probability of moving to the Shuffling scene rather than to the DecisionMaking scene
+    pub transition_probability: f64,
+    /// relative weight of a user deciding to take their item
+    pub take_weight: f64,
+    /// relative weight of a user deciding to observe the monitor
+    pub observe_weight: f64,
+    /// relative weight of a user deciding to do nothing
+    pub idle_weight: f64,
+    /// behavior of the users, the first profile applies to user 0 and so on, other users follow the weights above
+    pub profiles: Vec<UserProfile>,
+    /// probability that a user who wants to observe the monitor succeeds
+    pub observation_success_probability: f64,
+    /// positions of the locker a user glimpses when observing it, every position if none
+    pub partial_observation: Option<PartialObservation>,
+    /// probability that the monitor is faulty when a user observes it, showing an older snapshot than the told one
+    pub display_fault_probability: f64,
+    /// whether the player is told when the monitor is faulty, otherwise only the user sees the stale snapshot
+    pub announce_display_faults: bool,
+    /// probability that a user sees another user walking into the room of the monitor, in SecondOrder mode
+    pub witness_probability: f64,
+    /// probability that a prediction asks where another user thinks the user will go, in SecondOrder mode
+    pub second_order_probability: f64,
+    /// probability that a probe about a user is asked before a prediction
+    pub probe_probability: f64,
+    /// kinds of the probes to ask, drawn uniformly
+    pub probe_kinds: Vec<ProbeKind>,
+    /// probability that an unscored control probe about the position of an item is asked after a shuffle
+    pub control_probability: f64,
+    /// how many snapshots back the screen of Room 3 may show at most in Finite mode, unlimited if none
+    pub max_lookback: Option<usize>,
+    /// how many turns a player may play in one session, unlimited if none
+    pub turn_limit: Option<usize>,
+    /// how the answers to predictions and probes are read, answers that cannot be read are wrong
+    pub answer_policy: AnswerPolicy,
+    /// whether a prediction or a probe whose answer cannot be read is asked once more before it is scored
+    pub reprompt_unreadable: bool,
+    /// the turns to build with the scenario generator, played at random if none
+    pub scenario: Option<ScenarioSpec>,
+    /// the turn to play every turn, written by hand, see `Script::validate`
+    pub script: Option<Script>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            user_n: 5,
+            locker_n: 1,
+            shuffle_probability: 0.5,
+            locker_profiles: vec![],
+            rooms: vec![],
+            transition_probability: 0.5,
+            take_weight: 1.0,
+            observe_weight: 1.0,
+            idle_weight: 1.0,
+            profiles: vec![],
+            observation_success_probability: 0.5,
+            partial_observation: None,
+            display_fault_probability: 0.0,
+            announce_display_faults: true,
+            witness_probability: 0.5,
+            second_order_probability: 0.5,
+            probe_probability: 0.0,
+            probe_kinds: ProbeKind::ALL.to_vec(),
+            control_probability: 0.0,
+            max_lookback: None,
+            turn_limit: None,
+            answer_policy: AnswerPolicy::Strict,
+            reprompt_unreadable: false,
+            scenario: None,
+            script: None,
+        }
+    }
+}
+
+impl GameConfig {
+    /// load reads the config from a TOML or JSON file, depending on its extension, and validates it
+    pub fn load(path: &Path) -> Result<GameConfig, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Read(e.to_string()))?;
+        let config: GameConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?,
+            Some("toml") => toml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?,
+            _ => return Err(ConfigError::Read(format!("unsupported config file {}, expected .toml or .json", path.display()))),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// validate checks that the game can be played with the config
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.user_n == 0 {
+            return Err(ConfigError::Invalid("user_n must be at least 1".to_string()));
+        }
+        if self.locker_n == 0 || self.locker_n > self.user_n {
+            return Err(ConfigError::Invalid(format!("locker_n must be between 1 and user_n, got {}", self.locker_n)));
+        }
+        if self.locker_profiles.len() > self.locker_n {
+            return Err(ConfigError::Invalid(format!("{} locker profiles are given for {} lockers", self.locker_profiles.len(), self.locker_n)));
+        }
+        for (room, lockers) in self.rooms.iter().enumerate() {
+            if lockers.is_empty() || lockers.iter().any(|&locker_id| locker_id >= self.locker_n) {
+                return Err(ConfigError::Invalid(format!("room {} must show at least one locker, among the {} lockers", room, self.locker_n)));
+            }
+        }
+        for (name, p) in (0..self.locker_n).map(|locker_id| ("shuffle_probability", self.shuffle_probability(locker_id))).chain([
+            ("transition_probability", self.transition_probability),
+            ("observation_success_probability", self.observation_success_probability),
+            ("display_fault_probability", self.display_fault_probability),
+            ("witness_probability", self.witness_probability),
+            ("second_order_probability", self.second_order_probability),
+            ("probe_probability", self.probe_probability),
+            ("control_probability", self.control_probability),
+        ]) {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(ConfigError::Invalid(format!("{} must be between 0 and 1, got {}", name, p)));
+            }
+        }
+        // the game only moves on to the users' decisions when it leaves the Shuffling scene
+        if self.transition_probability == 1.0 {
+            return Err(ConfigError::Invalid("transition_probability must be less than 1, or users never make decisions".to_string()));
+        }
+        if self.profiles.len() > self.user_n {
+            return Err(ConfigError::Invalid(format!("{} profiles are given for {} users", self.profiles.len(), self.user_n)));
+        }
+        for user_id in 0..self.user_n {
+            let [take_weight, observe_weight, idle_weight] = self.decision_weights(user_id);
+            for (name, w) in [("take_weight", take_weight), ("observe_weight", observe_weight), ("idle_weight", idle_weight)] {
+                if !w.is_finite() || w < 0.0 {
+                    return Err(ConfigError::Invalid(format!("{} of user {} must be a non-negative number, got {}", name, user_id, w)));
+                }
+            }
+            // the game only ends when every user has taken their item
+            if take_weight == 0.0 {
+                return Err(ConfigError::Invalid(format!("take_weight of user {} must be positive, or the game never ends", user_id)));
+            }
+        }
+        if self.probe_probability > 0.0 && self.probe_kinds.is_empty() {
+            return Err(ConfigError::Invalid("probe_kinds must not be empty when probes are asked".to_string()));
+        }
+        if self.probe_kinds.contains(&ProbeKind::Control) {
+            return Err(ConfigError::Invalid("probe_kinds must not contain control, control probes are asked with control_probability".to_string()));
+        }
+        if self.partial_observation == Some(PartialObservation::Random(0)) {
+            return Err(ConfigError::Invalid("partial_observation must glimpse at least 1 position".to_string()));
+        }
+        if self.turn_limit == Some(0) {
+            return Err(ConfigError::Invalid("turn_limit must be at least 1".to_string()));
+        }
+        if self.scenario.is_some() && self.script.is_some() {
+            return Err(ConfigError::Invalid("the turns cannot be both generated and scripted".to_string()));
+        }
+        if let Some(scenario) = &self.scenario {
+            let locker_sizes: Vec<usize> = (0..self.locker_n).map(|locker_id| (locker_id..self.user_n).step_by(self.locker_n).count()).collect();
+            scenario.validate(self.user_n, &locker_sizes, &self.observation_rooms())?;
+        }
+        Ok(())
+    }
+
+    /// shuffle_probability is the probability that the locker malfunctions in the Shuffling scene
+    pub fn shuffle_probability(&self, locker_id: usize) -> f64 {
+        self.locker_profiles.get(locker_id).and_then(|p| p.shuffle_probability).unwrap_or(self.shuffle_probability)
+    }
+
+    /// observation_rooms are the lockers shown by each observation room
+    pub fn observation_rooms(&self) -> Vec<Vec<usize>> {
+        if self.rooms.is_empty() {
+            vec![(0..self.locker_n).collect()]
+        } else {
+            self.rooms.clone()
+        }
+    }
+
+    /// decision_weights are the weights of taking the item, observing the monitor and doing nothing of a user
+    pub fn decision_weights(&self, user_id: usize) -> [f64; 3] {
+        let profile = self.profiles.get(user_id);
+        [
+            profile.and_then(|p| p.take_weight).unwrap_or(self.take_weight),
+            profile.and_then(|p| p.observe_weight).unwrap_or(self.observe_weight),
+            profile.and_then(|p| p.idle_weight).unwrap_or(self.idle_weight),
+        ]
+    }
+}
+
+/// UserProfile is the behavior of a user, overriding the decision weights of the config.
+///
+/// For example, a user who never observes the monitor keeps believing the locker as they last saw it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserProfile {
+    pub take_weight: Option<f64>,
+    pub observe_weight: Option<f64>,
+    pub idle_weight: Option<f64>,
+}
+
+/// PartialObservation is the part of a locker a user glimpses when observing it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartialObservation {
+    /// Only the position of their own item, nothing of the other lockers
+    OwnItem,
+    /// The given number of random positions of each locker
+    Random(usize),
+}
+
+/// LockerProfile is the malfunction schedule of a locker, overriding the shuffle probability of the config.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockerProfile {
+    pub shuffle_probability: Option<f64>,
+}
+
+/// ConfigError is an enum that holds the reasons a config cannot be used.
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    Read(String),
+    Parse(String),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "Invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_valid() {
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_partial_toml() {
+        let config: GameConfig = toml::from_str("user_n = 3\nmax_lookback = 2").unwrap();
+        assert_eq!(config.user_n, 3);
+        assert_eq!(config.max_lookback, Some(2));
+        assert_eq!(config.shuffle_probability, GameConfig::default().shuffle_probability);
+        assert!(toml::from_str::<GameConfig>("users = 3").is_err());
+
+        let config: GameConfig = toml::from_str("observe_weight = 2.0\n[[profiles]]\nobserve_weight = 0.0").unwrap();
+        assert_eq!(config.decision_weights(0), [1.0, 0.0, 1.0]);
+        assert_eq!(config.decision_weights(1), [1.0, 2.0, 1.0]);
+
+        let config: GameConfig = toml::from_str("user_n = 3\n[scenario]\ntrue_belief_n = 1\nfalse_belief_n = 2").unwrap();
+        assert_eq!(config.scenario.as_ref().unwrap().staleness, 1);
+        assert_eq!(config.validate(), Ok(()));
+
+        let config: GameConfig = toml::from_str("partial_observation = { random = 2 }").unwrap();
+        assert_eq!(config.partial_observation, Some(PartialObservation::Random(2)));
+        let config: GameConfig = toml::from_str("partial_observation = \"own_item\"").unwrap();
+        assert_eq!(config.partial_observation, Some(PartialObservation::OwnItem));
+    }
+
+    #[test]
+    fn test_impossible_settings() {
+        let invalid = [
+            GameConfig { user_n: 0, ..Default::default() },
+            GameConfig { shuffle_probability: 1.5, ..Default::default() },
+            GameConfig { transition_probability: 1.0, ..Default::default() },
+            GameConfig { observe_weight: -1.0, ..Default::default() },
+            GameConfig { take_weight: 0.0, ..Default::default() },
+            GameConfig { turn_limit: Some(0), ..Default::default() },
+            GameConfig { probe_probability: 0.5, probe_kinds: vec![], ..Default::default() },
+            GameConfig { probe_kinds: vec![ProbeKind::Control], ..Default::default() },
+            GameConfig { profiles: vec![UserProfile { take_weight: Some(0.0), ..Default::default() }], ..Default::default() },
+            GameConfig { user_n: 1, profiles: vec![UserProfile::default(); 2], ..Default::default() },
+            GameConfig { locker_n: 6, ..Default::default() },
+            GameConfig { partial_observation: Some(PartialObservation::Random(0)), ..Default::default() },
+            GameConfig { display_fault_probability: -0.1, ..Default::default() },
+            GameConfig { scenario: Some(ScenarioSpec { true_belief_n: 1, ..Default::default() }), ..Default::default() },
+            GameConfig { locker_n: 2, rooms: vec![vec![0], vec![2]], ..Default::default() },
+            GameConfig { locker_n: 2, locker_profiles: vec![LockerProfile::default(), LockerProfile { shuffle_probability: Some(2.0) }], ..Default::default() },
+        ];
+        for config in invalid {
+            assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        }
+    }
+}
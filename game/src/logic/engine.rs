@@ -1,26 +1,30 @@
-use std::net::TcpStream;
+use std::collections::VecDeque;
 
 use indoc::formatdoc;
-use rand::rngs::{StdRng};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
 use rand::seq::{IteratorRandom, SliceRandom};
 use rand::Rng;
 use rand::SeedableRng;
 
 use crate::utils::shuffle::shuffle;
-use crate::utils::tcp::{Data, read_until_separator};
-use crate::utils::tcp::write_to_stream;
 use crate::utils::to_ordinal;
 
+use super::config::{GameConfig, PartialObservation};
 use super::user::UserCollection;
-use super::user::Decision;
+use super::user::{believed_item_idx, Decision};
 use super::locker::Locker;
+use super::message::{ControlScore, Message, PredictionRecord, ProbeAnswer, ProbeKind, ProbeScore};
+use super::scenario::Step;
 
 /// Scene is an enum that holds the possible scenes in the game.
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Scene {
     Init,           // Start the game, tell the player the game instruction
     DecisionMaking, // User should make a decision to (1) take item (2) observe (3) or nothing
     Shuffling,      // Shuffle the deck
-    Observing,        // User observe the status of the monitor
+    Observing,      // User observe the status of the monitor
+    Probing,        // The player answer a probe about the state of the game
     Predicting,     // The player predict the user's action
     End,            // tell the final result, and game over
 }
@@ -29,392 +33,1489 @@ enum Scene {
 /// State is a struct that holds the current state of the game.
 struct State {
     score: usize,
-    locker_snapshots: Vec<Locker>,
+    /// the snapshots of each locker, indexed by the id of the locker
+    locker_snapshots: Vec<Vec<Locker>>,
     users: UserCollection,
     user_decision: Decision,
+    /// the user whose belief about the taking user the player must predict, for second-order predictions
+    believer: Option<usize>,
+    /// the probe to answer before the prediction, with the user it is about
+    probe: Option<(ProbeKind, usize)>,
+    /// the results of the probes of the turn, by kind
+    probe_scores: Vec<ProbeScore>,
+    /// whether the last control probe of the turn was passed, if any was asked
+    last_control: Option<bool>,
+    /// the results of the predictions, depending on the last control probe
+    control_score: ControlScore,
+    /// the steps left to play in a scripted turn, the turn goes on at random once they are over
+    script: VecDeque<Step>,
+    /// the number of predictions whose answer could not be read
+    unreadable: usize,
+    /// whether the pending prediction or probe was asked once more, its answer being unreadable
+    reprompted: bool,
 }
 
+impl State {
+    fn new(users: UserCollection, locker_snapshots: Vec<Vec<Locker>>, script: VecDeque<Step>) -> State {
+        State {
+            score: 0,
+            user_decision: Decision::None,
+            believer: None,
+            probe: None,
+            probe_scores: vec![],
+            last_control: None,
+            control_score: ControlScore::default(),
+            users,
+            locker_snapshots,
+            script,
+            unreadable: 0,
+            reprompted: false,
+        }
+    }
+}
+
+/// Sight is what a user saw of a locker when observing its monitor.
+struct Sight {
+    locker_id: usize,
+    /// number of snapshots of the locker at the time of the observation
+    states_len: usize,
+    /// the snapshot the monitor was told to show
+    observed_state_idx: usize,
+    /// the snapshot the monitor actually showed, older than the told one if the monitor was faulty
+    shown_state_idx: usize,
+    /// the positions the user glimpsed, every position if none
+    positions: Option<Vec<usize>>,
+}
+
+/// GameMode is an enum that holds the belief history conditions of the game.
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum GameMode {
-    Zero,
-    Finite,
+    Zero,        // Users observe the latest state of the monitor
+    Finite,      // Users observe a snapshot no older than their belief, within the configured lookback
+    Infinite,    // Users observe a snapshot of any depth in the history of the monitor
+    SecondOrder, // Users observe the latest state of the monitor, and see each other doing so
 }
 
+impl GameMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Zero => "zero",
+            GameMode::Finite => "finite",
+            GameMode::Infinite => "infinite",
+            GameMode::SecondOrder => "second-order",
+        }
+    }
+}
 
-/// Game logic
-pub fn start(mode: GameMode) -> Box<dyn Fn(TcpStream) + Send + Sync> {
-    Box::new(move |mut stream: TcpStream| {
-        let mut rng = StdRng::seed_from_u64(1);
-
-        loop {
-            // game settings/options
-            let user_n = 5;
-
-            // init the game
-            let mut locker = Locker::new(user_n);
-            let mut users = UserCollection::new(user_n, 0);
-            for i in 0..user_n {
-                let user = &mut users.users[i];
-                locker.items[i].as_mut().unwrap().belongs_to = user.id;
-            }
-            locker.items.shuffle(&mut rng); // initial shuffle
-            let mut state = State {
-                score: 0,
-                user_decision: Decision::None,
-                users,
-                locker_snapshots: vec![locker],
-            };
-            let mut scene = Scene::Init;
-            loop {
-                match scene {
-                    // Start the game, tell the player the game instruction and game initial information
-                    Scene::Init => {
-                        const GAME_NAME: &str = "Pick the Right Stuff";
-                        let game_introduction;
-                        match mode {
-                            GameMode::Zero => {
-                                game_introduction = formatdoc! {"
-                    Welcome to, {}!
-
-                    In this game, you will play the role of a warehouse manager. The warehouse contains two rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. During the game, users may or may not enter the Room 2 to observe the monitor. By observing the monitor, users will update their beliefs about the position of their item.
-
-                    If a user successfully retrieves their item, you score a point and the item is removed from the locker.
-                    If a user retrieves the wrong item, the item is returned, the user contacts the system administrator to take the correct item, and you score no points.
-
-                    Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME};
-                            }
-                            GameMode::Finite => {
-                                game_introduction = formatdoc! {"
-                    Welcome to, {}!
+impl std::str::FromStr for GameMode {
+    type Err = String;
 
-                    In this game, you will play the role of a warehouse manager. The warehouse contains three rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. Additionally, Room 3 contains a screen which will randomly show a certain previous snapshot of the monitor located in Room 2. During the game, users may or may not enter the Room 3 to observe a certain snapshot of the monitor. By observing the snapshot, users will update their beliefs about the position of their item.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(GameMode::Zero),
+            "finite" => Ok(GameMode::Finite),
+            "infinite" => Ok(GameMode::Infinite),
+            "second-order" => Ok(GameMode::SecondOrder),
+            _ => Err(format!("invalid mode {}", s)),
+        }
+    }
+}
 
-                    If a user successfully retrieves their item, you score a point and the item is removed from the locker.
-                    If a user retrieves the wrong item, the item is returned, the user contacts the system administrator to take the correct item, and you score no points.
+/// Action is an input from the player, fed into the game by `Game::step`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    /// The raw answer of the player to the latest prompt
+    Answer(String),
+}
 
-                    Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME};
-                            }
-                        }
-                        let game_begin_info = formatdoc! {"
-                    Game Begins!
+/// Event is an output of the game, produced by `Game::step`.
+///
+/// It holds both the structured message and its rendering in natural language for the player.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Event {
+    pub message: Message,
+    pub content: String,
+}
 
-                    There are {} users. {}
+impl Event {
+    pub fn new(message: Message, content: String) -> Event {
+        Event { message, content }
+    }
 
-                    Now they leave the room.",
-                user_n,
-                (|| {
-                    let mut s = String::new();
-                    for user in state.users.users.iter() {
-                        s.push_str(&format!("User {} stores its item at the position {} of the locker. ", user.id, to_ordinal(state.locker_snapshots.last().unwrap().get_item_idx_by_belongs(user.id) as u32)));
-                    }
-                    s
-                })()
-                };
+    /// require_input tells whether the player must answer the event before the game can go on
+    pub fn require_input(&self) -> bool {
+        self.message.require_input()
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
 
-                        let all = formatdoc! {"
-                    {}
+/// Game is the I/O-free state machine of the game.
+///
+/// It is driven by `step`: the game advances through its scenes until the player has to answer a prompt,
+/// and returns everything that happened in between. The answer is then fed back by the next call to `step`.
+pub struct Game {
+    mode: GameMode,
+    config: GameConfig,
+    rng: StdRng,
+    state: State,
+    scene: Scene,
+    turns: usize,
+    awaiting_input: bool,
+    over: bool,
+}
+
+impl Game {
+    /// new creates a game from a config, which is expected to be valid (see `GameConfig::validate`)
+    pub fn new(mode: GameMode, config: GameConfig, seed: u64) -> Game {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let state = Game::init_state(&config, &mut rng);
+        Game {
+            mode,
+            config,
+            rng,
+            state,
+            scene: Scene::Init,
+            turns: 0,
+            awaiting_input: false,
+            over: false,
+        }
+    }
 
-                    ============
+    /// init_state places one item per user in their locker and shuffles each locker, or lays the lockers out as
+    /// scripted
+    fn init_state(config: &GameConfig, rng: &mut StdRng) -> State {
+        if let Some(script) = &config.script {
+            let users = UserCollection::with_lockers(&script.user_lockers(), &script.lockers.iter().map(|locker| locker.len()).collect::<Vec<_>>());
+            let locker_snapshots = script.lockers.iter().map(|layout| vec![Locker::from_layout(layout)]).collect();
+            return State::new(users, locker_snapshots, script.steps.iter().cloned().collect());
+        }
+        let users = UserCollection::new(config.user_n, config.locker_n);
+        let mut locker_snapshots = vec![];
+        for locker_id in 0..config.locker_n {
+            let owners: Vec<usize> = users.users.iter().filter(|user| user.locker_id == locker_id).map(|user| user.id).collect();
+            let mut locker = Locker::new(owners.len());
+            for (i, owner) in owners.into_iter().enumerate() {
+                locker.items[i].as_mut().unwrap().belongs_to = owner;
+            }
+            locker.items.shuffle(rng); // initial shuffle
+            locker_snapshots.push(vec![locker]);
+        }
+        let script = match &config.scenario {
+            Some(scenario) => {
+                let lockers: Vec<Vec<Option<usize>>> = locker_snapshots.iter().map(|snapshots| snapshots[0].layout()).collect();
+                let user_lockers: Vec<usize> = users.users.iter().map(|user| user.locker_id).collect();
+                scenario.generate(&lockers, &user_lockers, &config.observation_rooms(), rng).into()
+            }
+            None => VecDeque::new(),
+        };
+        State::new(users, locker_snapshots, script)
+    }
+
+    /// turn is the number of turns played before the current one
+    pub fn turn(&self) -> usize {
+        self.turns
+    }
+
+    /// snapshots are the states of each locker since the beginning of the current turn
+    pub fn snapshots(&self) -> &[Vec<Locker>] {
+        &self.state.locker_snapshots
+    }
+
+    /// expected_answer is the right answer to the pending prompt, if it is a prediction or a probe: the position the
+    /// user believes, or the believer thinks the user believes, or the answer of the probe
+    pub fn expected_answer(&self) -> Option<ProbeAnswer> {
+        if !self.awaiting_input {
+            return None;
+        }
+        self.expected_answer_of_scene()
+    }
 
-                    {}
-                ",
-                    game_introduction,
-                    game_begin_info
+    /// expected_answer_of_scene is the right answer to the prediction or the probe of the scene, if any
+    fn expected_answer_of_scene(&self) -> Option<ProbeAnswer> {
+        match self.scene {
+            Scene::Predicting => {
+                let user_id = self.taking_user_id();
+                let locker_id = self.state.users.get_by_id(user_id).unwrap().locker_id;
+                let believer = self.state.users.get_by_id(self.state.believer.unwrap_or(user_id)).unwrap();
+                let position_state_idx = match self.state.believer {
+                    Some(_) => &believer.others_inmind_position_state_idx[user_id],
+                    None => &believer.inmind_position_state_idx[locker_id],
                 };
-                        let data = Data::new(false, all);
-                        write_to_stream(&mut stream, data).unwrap();
+                Some(ProbeAnswer::Number(believed_item_idx(&self.state.locker_snapshots[locker_id], position_state_idx, user_id)))
+            }
+            Scene::Probing => {
+                let (kind, user_id) = self.state.probe.unwrap();
+                Some(self.probe_answer(kind, user_id))
+            }
+            _ => None,
+        }
+    }
 
-                        // change to shuffling state
-                        scene = Scene::Shuffling;
-                    }
-                    // User should make a decision among (1) take item (2) observe (3) or nothing
-                    Scene::DecisionMaking => {
-                        let user = state.users.users.choose(&mut rng).unwrap();
-                        let decision: Decision = Decision::rand_choose(&mut rng, user.id);
-                        state.user_decision = decision;
-
-                        // randomly change to one of the following states
-                        // 1. Shuffling (must if user want to take the item)
-                        // 2. observing (must if user want to observe the status of the monitor)
-                        // 3. Change to Shuffling or DecisionMaking (if user do nothing)
-                        match decision {
-                            Decision::TakeItem { .. } => {
-                                scene = Scene::Predicting;
-                            }
-                            Decision::Observe { .. } => {
-                                scene = Scene::Observing;
-                            }
-                            Decision::None => match rng.gen_bool(0.5) {
-                                true => {
-                                    scene = Scene::Shuffling;
-                                }
-                                false => {
-                                    scene = Scene::DecisionMaking;
-                                }
-                            },
+    /// is_over tells whether the player has quit the game
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /// step advances the game until the player is asked for an input (the last event then requires an input)
+    /// or the game is over.
+    ///
+    /// `input` answers the pending prompt. It is ignored if no prompt is pending, and the pending prompt is
+    /// emitted again if it is called without an input.
+    pub fn step(&mut self, input: Option<Action>) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut input = if self.awaiting_input { input } else { None };
+        while !self.over {
+            match self.scene {
+                Scene::Init => self.init(&mut events),
+                Scene::DecisionMaking | Scene::Shuffling if !self.state.script.is_empty() => self.scripting(&mut events),
+                Scene::DecisionMaking => self.decision_making(),
+                Scene::Shuffling => self.shuffling(&mut events),
+                Scene::Observing => self.observing(&mut events),
+                Scene::Probing => match input.take() {
+                    Some(Action::Answer(answer)) if self.awaiting_input => {
+                        self.awaiting_input = false;
+                        if !self.reprompting(&answer, &mut events) {
+                            self.probing(&answer, &mut events);
                         }
                     }
-                    // Locker shuffles the items
-                    Scene::Shuffling => {
-                        if let Decision::TakeItem { from } = state.user_decision {
-                            // user try to take the item, must shuffle the items
-                            let user = state.users.get_mut_by_id(from).unwrap();
-                            let user_current_inmind_locker_idx = user.inmind_locker_state_idx;
-                            let mut user_current_inmind_locker = state.locker_snapshots[user_current_inmind_locker_idx].clone();
-                            shuffle(&mut user_current_inmind_locker.items, &mut rng);
-                            state.locker_snapshots.push(user_current_inmind_locker.clone());
-                            let last_snapshot = state.locker_snapshots.last().unwrap();
-                            let info = formatdoc! {"
-                        The locker is malfunctioning and randomly resetting the positions of the items in the locker...
-                        The locker has returned to normal.
-                        From the monitor, you can see the content of the locker:
-                        {}
-                        ",
-                        (|| {
-                            let mut s = String::new();
-                            for (i, item) in last_snapshot.items.iter().enumerate() {
-                                if let Some(item) = item {
-                                    s.push_str(&format!("The position {} stores the item of User {}.\n", to_ordinal(i as u32), item.belongs_to as u32));
-                                }else{
-                                    s.push_str(&format!("The position {} is empty.\n", to_ordinal(i as u32)));
-                                }
-                            }
-                            s
-                        })()
-                    };
-                            let data = Data::new(false, info);
-                            write_to_stream(&mut stream, data).unwrap();
-                            // change to Predicting state
-                            scene = Scene::Predicting;
-                        } else {
-                            // shuffle the items or not depends on the random state
-                            match rng.gen_bool(0.5) {
-                                true => {
-                                    let mut last_snapshot = state.locker_snapshots.last().unwrap().clone();
-                                    shuffle(&mut last_snapshot.items, &mut rng);
-                                    state.locker_snapshots.push(last_snapshot);
-                                    let info = formatdoc! {"
-                                The locker is malfunctioning and randomly resetting the positions of the items in the locker...
-                                The locker has returned to normal.
-                                From the monitor, you can see the content of the locker:
-                                {}
-                                ",
-                                (|| {
-                                    let mut s = String::new();
-                                    let last_snapshot = state.locker_snapshots.last().unwrap();
-                                    for (i, item) in last_snapshot.items.iter().enumerate() {
-                                        if let Some(item) = item {
-                                            s.push_str(&format!("The position {} stores the item of User {}.\n", to_ordinal(i as u32), item.belongs_to as u32));
-                                        }else{
-                                            s.push_str(&format!("The position {} box is empty.\n", to_ordinal(i as u32)));
-                                        }
-                                    }
-                                    s
-                                })()
-                            };
-                                    let data = Data::new(false, info);
-                                    write_to_stream(&mut stream, data).unwrap();
-                                }
-                                _ => {}
-                            }
-                            // randomly change to one of the following states
-                            // 1. DecisionMaking
-                            // 2. Shuffling
-                            match rng.gen_bool(0.5) {
-                                true => {
-                                    scene = Scene::DecisionMaking;
-                                }
-                                false => {
-                                    scene = Scene::Shuffling;
-                                }
-                            }
+                    _ => {
+                        self.awaiting_input = true;
+                        events.push(self.probe_request());
+                        break;
+                    }
+                },
+                Scene::Predicting => match input.take() {
+                    Some(Action::Answer(answer)) if self.awaiting_input => {
+                        self.awaiting_input = false;
+                        if !self.reprompting(&answer, &mut events) {
+                            self.predicting(&answer, &mut events);
                         }
                     }
-                    // User observe the status of the monitor by directly observing (For Zero) or by snapshot (For Finite)
-                    Scene::Observing => {
-                        let decision = state.user_decision;
-                        let user_id = match decision {
-                            Decision::Observe { from } => from,
-                            _ => panic!("Invalid decision"),
-                        };
-                        let request_result: bool = rng.gen_bool(0.5);
-                        match request_result {
-                            true => {
-                                // user can observe the state of the monitor
-                                match mode {
-                                    GameMode::Zero => {
-                                        let info = format!(
-                                            "User {} walks into the Room 2 and leaves the room after observing the monitor.\n",
-                                            user_id
-                                        );
-                                        let user = state.users.get_mut_by_id(user_id).unwrap();
-                                        user.inmind_locker_state_idx = state.locker_snapshots.len() - 1;
-                                        let data = Data::new(false, info);
-                                        write_to_stream(&mut stream, data).unwrap();
-                                    }
-                                    GameMode::Finite => {
-                                        let info1 = format!(
-                                            "User {} walks into the Room 3 and is observing the snapshot of the monitor...\n",
-                                            user_id
-                                        );
-                                        let user = state.users.get_mut_by_id(user_id).unwrap();
-                                        let states_len = state.locker_snapshots.len();
-                                        let range = user.inmind_locker_state_idx..states_len;
-                                        let observed_state_idx = range.choose(&mut rng).unwrap();
-                                        user.inmind_locker_state_idx = observed_state_idx;
-                                        let info2;
-                                        if observed_state_idx == states_len - 1 {
-                                            info2 = format!("User {} observes the snapshot which depicts the last state of the monitor and leaves the room.\n", user_id);
-                                        } else {
-                                            info2 =
-                                                format!("User {} observes the snapshot which depicts the {}-to-last state of the monitor and leaves the room.\n", user_id, to_ordinal((states_len - observed_state_idx) as u32))
-                                        }
-                                        let info = format!("{}\n{}", info1, info2);
-                                        let data = Data::new(false, info);
-                                        write_to_stream(&mut stream, data).unwrap();
-                                    }
-                                }
-                            }
-                            _ => {}
+                    _ => {
+                        self.awaiting_input = true;
+                        events.push(self.prediction_request());
+                        break;
+                    }
+                },
+                Scene::End => match input.take() {
+                    Some(Action::Answer(answer)) if self.awaiting_input => {
+                        self.awaiting_input = false;
+                        self.end(&answer);
+                    }
+                    _ => {
+                        if !self.awaiting_input {
+                            events.push(self.statistics());
                         }
-                        state.user_decision = Decision::None;
-
-                        // randomly change to one of the following states
-                        //1. Shuffling
-                        //2. DecisionMaking
-                        match rng.gen_bool(0.5) {
-                            true => {
-                                scene = Scene::Shuffling;
-                            }
-                            false => {
-                                scene = Scene::DecisionMaking;
-                            }
+                        if self.is_last_turn() {
+                            // no more turns to play, the game is over without asking
+                            self.over = true;
+                            break;
                         }
+                        self.awaiting_input = true;
+                        events.push(Event::new(Message::PlayAgain, "Do you want to play another turn?(Y/n)\n".to_string()));
+                        break;
                     }
-                    // The player predict the user's belief
-                    Scene::Predicting => {
-                        let user_id = match state.user_decision {
-                            Decision::TakeItem { from } => from,
-                            _ => panic!("Invalid decision"),
-                        };
-                        let info1 = format!("User {} is coming to Room 1 to take his/her item...\n", user_id);
-                        // real item index in the locker
-                        let real_item_idx = state.locker_snapshots.last().unwrap().get_item_idx_by_belongs(user_id);
-                        // inmind item index in the locker
-                        let inmind_locker_idx = state.users.get_mut_by_id(user_id).unwrap().inmind_locker_state_idx;
-                        let inmind_item_idx = state.locker_snapshots[inmind_locker_idx].get_item_idx_by_belongs(user_id);
-                        // ask LLM to make prediction
-                        let info2 = formatdoc! {"
-                    You should only answer the position of the item the user will go to retrieve their item (e.g. 0 for the 0th, 1 for the 1st, 2 for the 2nd...).
-                    For example, if you think the user will go to position 0th to retrieve their item, you should only answer in single number '0'.
-                    Please make your prediction:"
+                },
+            }
+        }
+        events
+    }
+
+    // Start the game, tell the player the game instruction and game initial information
+    fn init(&mut self, events: &mut Vec<Event>) {
+        const GAME_NAME: &str = "Pick the Right Stuff";
+        let game_introduction = match self.mode {
+            GameMode::Zero => formatdoc! {"
+                Welcome to, {}!
+
+                In this game, you will play the role of a warehouse manager. The warehouse contains two rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. During the game, users may or may not enter the Room 2 to observe the monitor. By observing the monitor, users will update their beliefs about the position of their item.
+
+                If a user successfully retrieves their item, you score a point and the item is removed from the locker.
+                If a user retrieves the wrong item, the item is returned, the user contacts the system administrator to take the correct item, and you score no points.
+
+                Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME},
+            GameMode::Finite => formatdoc! {"
+                Welcome to, {}!
+
+                In this game, you will play the role of a warehouse manager. The warehouse contains three rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. Additionally, Room 3 contains a screen which will randomly show a certain previous snapshot of the monitor located in Room 2. During the game, users may or may not enter the Room 3 to observe a certain snapshot of the monitor. By observing the snapshot, users will update their beliefs about the position of their item.
+
+                If a user successfully retrieves their item, you score a point and the item is removed from the locker.
+                If a user retrieves the wrong item, the item is returned, the user contacts the system administrator to take the correct item, and you score no points.
+
+                Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME},
+            GameMode::Infinite => formatdoc! {"
+                Welcome to, {}!
+
+                In this game, you will play the role of a warehouse manager. The warehouse contains three rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. Additionally, Room 3 contains a screen which keeps the whole history of the monitor located in Room 2 since the game began, and will randomly show a snapshot from any moment of this history, even one older than what a user has seen before. During the game, users may or may not enter the Room 3 to observe a certain snapshot of the monitor. By observing the snapshot, users will update their beliefs about the position of their item to the one depicted by the snapshot, whatever its age.
+
+                If a user successfully retrieves their item, you score a point and the item is removed from the locker.
+                If a user retrieves the wrong item, the item is returned, the user contacts the system administrator to take the correct item, and you score no points.
+
+                Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME},
+            GameMode::SecondOrder => formatdoc! {"
+                Welcome to, {}!
+
+                In this game, you will play the role of a warehouse manager. The warehouse contains two rooms. Room 1 is used for storing items, with each item stored in a certain position inside the opaque locker. You are situated in the Room 2, which contains a monitor that allows you to see the content of the opaque locker located in the Room 1 through the camera inside the opaque locker. Due to malfunctions in the locker system, it randomly resets the positions of the items in the opaque locker from time to time. To ensure that each user retrieves their stored item correctly, when a user comes to retrieve an item, you are required to predict the position of the item the user believes (the user will always retrieve their item based on the position they last believed). You only need to tell the system which position inside the locker the user will go to retrieve their item and then the locker system will automatically swap the item at that location with the one belonging to the user. During the game, users may or may not enter the Room 2 to observe the monitor. By observing the monitor, users will update their beliefs about the position of their item. The users wait in a hallway, from where they may see another user walking into the Room 2: they then know what this user has seen, but a user who did not see it still thinks this user believes what they believed before.
+
+                Sometimes, you will rather be asked to predict the position where another user thinks the user will go to retrieve their item, according to what this other user has seen.
+
+                If your prediction is correct, you score a point. Either way, the user retrieves their item, with the help of the system administrator if they went to the wrong position, and the item is removed from the locker.
+
+                Indeed, this is a problematic locker system, but you are hoped to be an excellent warehouse manager!",GAME_NAME},
+        };
+        let game_introduction = if self.config.locker_n > 1 {
+            let mut rooms = String::new();
+            if self.config.rooms.len() > 1 {
+                rooms.push_str("Users observe the lockers from different rooms:");
+                for (room, lockers) in self.config.observation_rooms().iter().enumerate() {
+                    let lockers: Vec<String> = lockers.iter().map(|&locker_id| self.locker_name(locker_id)).collect();
+                    rooms.push_str(&format!(" the {} shows the {},", self.room_name(room), lockers.join(" and the ")));
+                }
+                rooms.pop();
+                rooms.push_str(". A user only updates their beliefs about the lockers shown in the room they enter.");
+            }
+            formatdoc! {"
+                {}
+
+                Actually, the Room 1 contains {} opaque lockers, from Locker 0 to Locker {}, each with its own camera, its own monitor and its own malfunctions. Users store their items in different lockers, and only care about the locker storing their item. {}",
+                game_introduction,
+                self.config.locker_n,
+                self.config.locker_n - 1,
+                rooms
+            }
+        } else {
+            game_introduction
+        };
+        let game_introduction = if self.config.partial_observation.is_some() {
+            formatdoc! {"
+                {}
+
+                The monitors are blurry: a user observing a monitor only makes out some positions of the locker, and remembers, position by position, the last content they saw there. A user goes to the position where they saw their item most recently. If every position where they saw their item has shown another content since, they go to the position they have seen the least recently, the one with the smallest number if several.",
+                game_introduction
+            }
+        } else {
+            game_introduction
+        };
+        let mut stores = String::new();
+        for user in self.state.users.users.iter() {
+            let locker = self.state.locker_snapshots[user.locker_id].last().unwrap();
+            stores.push_str(&format!("User {} stores its item at the position {} of the {}. ", user.id, to_ordinal(locker.get_item_idx_by_belongs(user.id) as u32), self.locker_name(user.locker_id)));
+        }
+        let game_begin_info = formatdoc! {"
+            Game Begins!
+
+            There are {} users. {}
+
+            Now they leave the room.",
+            self.config.user_n,
+            stores
+        };
+
+        let all = formatdoc! {"
+            {}
+
+            ============
+
+            {}
+            ",
+            game_introduction,
+            game_begin_info
+        };
+        let intro = Message::Intro {
+            n_users: self.config.user_n,
+            lockers: self.state.locker_snapshots.iter().map(|snapshots| snapshots.last().unwrap().layout()).collect(),
+        };
+        events.push(Event::new(intro, all));
+
+        // change to shuffling state
+        self.scene = Scene::Shuffling;
+    }
+
+    // User should make a decision among (1) take item (2) observe (3) or nothing
+    fn decision_making(&mut self) {
+        let user = self.state.users.users.choose(&mut self.rng).unwrap();
+        let decision: Decision = Decision::rand_choose(&mut self.rng, user.id, self.config.decision_weights(user.id));
+        self.state.user_decision = decision;
+        if let (Decision::TakeItem { from }, GameMode::SecondOrder) = (decision, self.mode) {
+            // ask where another user thinks the user will go rather than where the user will go
+            self.state.believer = if self.rng.gen_bool(self.config.second_order_probability) {
+                self.state.users.users.iter().map(|user| user.id).filter(|&id| id != from).choose(&mut self.rng)
+            } else {
+                None
+            };
+        }
+
+        // randomly change to one of the following states
+        // 1. Shuffling (must if user want to take the item)
+        // 2. observing (must if user want to observe the status of the monitor)
+        // 3. Change to Shuffling or DecisionMaking (if user do nothing)
+        self.scene = match decision {
+            Decision::TakeItem { .. } => self.probe_or_predict(),
+            Decision::Observe { .. } => Scene::Observing,
+            Decision::None => self.next_scene(),
+        };
+    }
+
+    /// probe_or_predict randomly asks a probe before the prediction of the taking user
+    fn probe_or_predict(&mut self) -> Scene {
+        if self.config.probe_probability > 0.0 && self.rng.gen_bool(self.config.probe_probability) {
+            let kind = *self.config.probe_kinds.choose(&mut self.rng).unwrap();
+            let user_id = self.state.users.users.choose(&mut self.rng).unwrap().id;
+            self.state.probe = Some((kind, user_id));
+            Scene::Probing
+        } else {
+            Scene::Predicting
+        }
+    }
+
+    /// scripting plays the next step of a scripted turn
+    fn scripting(&mut self, events: &mut Vec<Event>) {
+        match self.state.script.pop_front().unwrap() {
+            Step::Shuffle { locker_id, locker } => {
+                let snapshots = &mut self.state.locker_snapshots[locker_id];
+                let mut last_snapshot = snapshots.last().unwrap().clone();
+                last_snapshot.rearrange(&locker);
+                snapshots.push(last_snapshot);
+                events.push(self.shuffle_event(locker_id));
+                self.scene = if self.control_probe(&[locker_id]) { Scene::Probing } else { Scene::DecisionMaking };
+            }
+            Step::Observe { user_id, room, snapshots_ago, positions, witnesses } => {
+                let mut observed = vec![];
+                for locker_id in self.config.observation_rooms().swap_remove(room) {
+                    let states_len = self.state.locker_snapshots[locker_id].len();
+                    let observed_state_idx = (states_len - 1).saturating_sub(snapshots_ago);
+                    let sight = Sight { locker_id, states_len, observed_state_idx, shown_state_idx: observed_state_idx, positions: positions.clone() };
+                    self.look(user_id, &sight);
+                    observed.push(sight);
+                }
+                self.tell_observation(user_id, room, &observed, events);
+                if self.mode == GameMode::SecondOrder {
+                    self.witnessing(user_id, room, &observed, &witnesses, events);
+                }
+                self.scene = Scene::DecisionMaking;
+            }
+            Step::Take { user_id, believer_id, probe } => {
+                self.state.user_decision = Decision::TakeItem { from: user_id };
+                self.state.believer = believer_id;
+                self.scene = match probe {
+                    Some(kind) => {
+                        self.state.probe = Some((kind, user_id));
+                        Scene::Probing
+                    }
+                    None => self.probe_or_predict(),
                 };
-                        let info = format!("{}\n{}", info1, info2);
-                        let data = Data::new(true, info);
-                        write_to_stream(&mut stream, data).unwrap();
-
-                        // get the prediction from the player
-                        let input = read_until_separator(&mut stream).expect("Failed to read from stream");
-                        let input = String::from_utf8(input).unwrap();
-                        let input = Data::from_json(&input);
-                        let predicted_inmind_item_idx: Option<usize> = input.content().trim().parse().ok();
-
-                        if predicted_inmind_item_idx.is_some()
-                            && predicted_inmind_item_idx.unwrap() as usize == inmind_item_idx
-                        {
-                            let info = format!(
-                                "Your prediction is correct! Item in the position {} is exchanged with the correct item in the position {}. User {} successfully retrieved the item from the correct position. You score a point!\n",
-                                to_ordinal(inmind_item_idx as u32),
-                                to_ordinal(real_item_idx as u32),
-                                user_id
-                            );
-                            let data = Data::new(false, info);
-                            write_to_stream(&mut stream, data).unwrap();
-                            state.score += 1;
-                        } else {
-                            let info = format!(
-                                "Your prediction is wrong! The administrator is intervening... Item in the position {} is exchanged with the correct item in the position {}. User {} retrieved the item with the help of the administrator. You score no points.\n",
-                                to_ordinal(inmind_item_idx as u32),
-                                to_ordinal(real_item_idx as u32),
-                                user_id
-                            );
-                            let data = Data::new(false, info);
-                            write_to_stream(&mut stream, data).unwrap();
-                        }
+            }
+        }
+    }
 
-                        state.locker_snapshots.push(state.locker_snapshots.last().unwrap().clone());
-                        state.locker_snapshots.last_mut().unwrap().exchange_items(real_item_idx, inmind_item_idx);
-                        state.locker_snapshots.last_mut().unwrap().remove_item(inmind_item_idx);
-                        state.users.remove_by_id(user_id);
-                        state.user_decision = Decision::None;
-
-                        // tell the LLM the current state of the locker
-                        let info = formatdoc! {"
-                                Now, from the monitor, you can see the content of the locker:
-                                {}
-                                ",
-                                (|| {
-                                    let mut s = String::new();
-                                    let last_snapshot = state.locker_snapshots.last().unwrap();
-                                    for (i, item) in last_snapshot.items.iter().enumerate() {
-                                        if let Some(item) = item {
-                                            s.push_str(&format!("The position {} stores the item of User {}.\n", to_ordinal(i as u32), item.belongs_to as u32));
-                                        }else{
-                                            s.push_str(&format!("The position {} box is empty.\n", to_ordinal(i as u32)));
-                                        }
-                                    }
-                                    s
-                                })()
-                            };
-                        write_to_stream(&mut stream, Data::new(false, info)).unwrap();
-                        // randomly change to one of the following states
-                        //1. Shuffling
-                        //2. DecisionMaking
-                        //3. End (if and only if there is no items left)
-                        if state.users.is_empty() {
-                            scene = Scene::End;
-                        } else {
-                            match rng.gen_bool(0.5) {
-                                true => {
-                                    scene = Scene::Shuffling;
-                                }
-                                false => {
-                                    scene = Scene::DecisionMaking;
-                                }
-                            }
-                        }
+    // Locker shuffles the items
+    fn shuffling(&mut self, events: &mut Vec<Event>) {
+        if let Decision::TakeItem { from } = self.state.user_decision {
+            // user try to take the item, must shuffle the items
+            let user = self.state.users.get_by_id(from).unwrap();
+            let locker_id = user.locker_id;
+            let snapshots = &mut self.state.locker_snapshots[locker_id];
+            let mut user_current_inmind_locker = snapshots[user.inmind_own_locker_state_idx()].clone();
+            shuffle(&mut user_current_inmind_locker.items, &mut self.rng);
+            snapshots.push(user_current_inmind_locker);
+            events.push(self.shuffle_event(locker_id));
+            // change to Predicting state
+            self.scene = Scene::Predicting;
+        } else {
+            // each locker shuffles its items or not depending on the random state
+            let mut shuffled = vec![];
+            for locker_id in 0..self.config.locker_n {
+                if self.rng.gen_bool(self.config.shuffle_probability(locker_id)) {
+                    let snapshots = &mut self.state.locker_snapshots[locker_id];
+                    let mut last_snapshot = snapshots.last().unwrap().clone();
+                    shuffle(&mut last_snapshot.items, &mut self.rng);
+                    snapshots.push(last_snapshot);
+                    events.push(self.shuffle_event(locker_id));
+                    shuffled.push(locker_id);
+                }
+            }
+            if self.control_probe(&shuffled) {
+                self.scene = Scene::Probing;
+                return;
+            }
+            // randomly change to one of the following states
+            // 1. DecisionMaking
+            // 2. Shuffling
+            self.scene = if self.rng.gen_bool(1.0 - self.config.transition_probability) { Scene::DecisionMaking } else { Scene::Shuffling };
+        }
+    }
+
+    /// control_probe randomly chooses to check that the player keeps track of the shuffled lockers, the game goes on
+    /// once answered
+    fn control_probe(&mut self, shuffled: &[usize]) -> bool {
+        let candidates: Vec<usize> = self.state.users.users.iter().filter(|user| shuffled.contains(&user.locker_id)).map(|user| user.id).collect();
+        if !candidates.is_empty() && self.config.control_probability > 0.0 && self.rng.gen_bool(self.config.control_probability) {
+            let user_id = *candidates.choose(&mut self.rng).unwrap();
+            self.state.probe = Some((ProbeKind::Control, user_id));
+            return true;
+        }
+        false
+    }
+
+    // User observe the status of the monitor by directly observing (For Zero) or by snapshot (For Finite and Infinite)
+    fn observing(&mut self, events: &mut Vec<Event>) {
+        let user_id = match self.state.user_decision {
+            Decision::Observe { from } => from,
+            _ => panic!("Invalid decision"),
+        };
+        let request_result: bool = self.rng.gen_bool(self.config.observation_success_probability);
+        if request_result {
+            // user can observe the state of the monitor, in one of the observation rooms
+            let mut rooms = self.config.observation_rooms();
+            let room = if rooms.len() > 1 { self.rng.gen_range(0..rooms.len()) } else { 0 };
+            let lockers = rooms.swap_remove(room);
+            let mut observed = vec![];
+            for locker_id in lockers {
+                let states_len = self.state.locker_snapshots[locker_id].len();
+                let observed_state_idx = match self.mode {
+                    GameMode::Zero | GameMode::SecondOrder => states_len - 1,
+                    GameMode::Finite => {
+                        // the screen shows at most `max_lookback` snapshots back, but never older than the user's belief
+                        let oldest_shown = match self.config.max_lookback {
+                            Some(lookback) => (states_len - 1).saturating_sub(lookback),
+                            None => 0,
+                        };
+                        let user = self.state.users.get_by_id(user_id).unwrap();
+                        let range = user.inmind_locker_state_idx[locker_id].max(oldest_shown)..states_len;
+                        range.choose(&mut self.rng).unwrap()
                     }
-                    // tell the final result, and game over
-                    Scene::End => {
-                        // tell the final result, and game over
-                        let statistics = formatdoc! {
-                    "Correct: {}
-                    Final score: {}
-                    ",
-                    state.score,
-                    state.score * 100 / user_n
+                    // no cap on the history, the user may even go back to an older belief
+                    GameMode::Infinite => (0..states_len).choose(&mut self.rng).unwrap(),
                 };
-                        let info = formatdoc! {"
-                    {}
-                    Game Over!
-                    Do you want to play another turn?(Y/n)
-                ", statistics};
-                        let data = Data::new(true, info);
-                        write_to_stream(&mut stream, data).unwrap();
-                        let answer = read_until_separator(&mut stream).expect("Failed to read from stream");
-                        let answer = String::from_utf8(answer).unwrap();
-                        let answer = Data::from_json(&answer);
-                        let answer: String = answer.content().trim().to_string();
-                        match answer.as_str() {
-                            "N" | "n" => {
-                                return;
+                // a faulty monitor shows an older snapshot, the user believes what it shows
+                let faulty = self.config.display_fault_probability > 0.0 && observed_state_idx > 0 && self.rng.gen_bool(self.config.display_fault_probability);
+                let shown_state_idx = if faulty { self.rng.gen_range(0..observed_state_idx) } else { observed_state_idx };
+                let positions = match self.config.partial_observation {
+                    None => None,
+                    Some(PartialObservation::OwnItem) if locker_id == self.state.users.get_by_id(user_id).unwrap().locker_id => {
+                        Some(vec![self.state.locker_snapshots[locker_id][shown_state_idx].get_item_idx_by_belongs(user_id)])
+                    }
+                    // the user does not look at the lockers not storing their item
+                    Some(PartialObservation::OwnItem) => continue,
+                    Some(PartialObservation::Random(k)) => {
+                        let positions_n = self.state.locker_snapshots[locker_id][shown_state_idx].items.len();
+                        let mut positions = sample(&mut self.rng, positions_n, k.min(positions_n)).into_vec();
+                        positions.sort();
+                        Some(positions)
+                    }
+                };
+                let sight = Sight { locker_id, states_len, observed_state_idx, shown_state_idx, positions };
+                self.look(user_id, &sight);
+                observed.push(sight);
+            }
+            self.tell_observation(user_id, room, &observed, events);
+            if self.mode == GameMode::SecondOrder && !observed.is_empty() {
+                let witnesses: Vec<usize> = self.state.users.users.iter().map(|user| user.id).filter(|&id| id != user_id && self.rng.gen_bool(self.config.witness_probability)).collect();
+                self.witnessing(user_id, room, &observed, &witnesses, events);
+            }
+        }
+        self.state.user_decision = Decision::None;
+
+        // randomly change to one of the following states
+        //1. Shuffling
+        //2. DecisionMaking
+        self.scene = self.next_scene();
+    }
+
+    /// look updates the beliefs of the user with what they saw of a locker
+    fn look(&mut self, user_id: usize, sight: &Sight) {
+        let user = self.state.users.get_mut_by_id(user_id).unwrap();
+        user.observe(sight.locker_id, sight.shown_state_idx, sight.positions.as_deref());
+        user.others_inmind_position_state_idx[user_id] = user.inmind_position_state_idx[user.locker_id].clone();
+    }
+
+    /// tell_observation narrates what the user saw in the observation room
+    fn tell_observation(&self, user_id: usize, room: usize, observed: &[Sight], events: &mut Vec<Event>) {
+        let room_name = self.room_name(room);
+        let lockers: Vec<usize> = observed.iter().map(|sight| sight.locker_id).collect();
+        for (i, Sight { locker_id, states_len, observed_state_idx, shown_state_idx, positions }) in observed.iter().enumerate() {
+            let monitor = self.monitor_name(*locker_id);
+            let leaves = if i == observed.len() - 1 { " and leaves the room" } else { "" };
+            let info = match self.mode {
+                GameMode::Zero | GameMode::SecondOrder => {
+                    let seen = match positions {
+                        None => format!("the {}", monitor),
+                        Some(positions) => format!("the {} of the {}", describe_positions(positions), monitor),
+                    };
+                    if observed.len() == 1 {
+                        let glimpsing = if positions.is_some() { "glimpsing" } else { "observing" };
+                        format!("User {} walks into the {} and leaves the room after {} {}.\n", user_id, room_name, glimpsing, seen)
+                    } else {
+                        let enters = if i == 0 { format!("User {} walks into the {}.\n", user_id, room_name) } else { String::new() };
+                        let observes = if positions.is_some() { "glimpses" } else { "observes" };
+                        format!("{}User {} {} {}{}.\n", enters, user_id, observes, seen, leaves)
+                    }
+                }
+                GameMode::Finite | GameMode::Infinite => {
+                    let observes = match positions {
+                        None => "observes the snapshot".to_string(),
+                        Some(positions) => format!("glimpses the {} of the snapshot", describe_positions(positions)),
+                    };
+                    let info2 = if *observed_state_idx == states_len - 1 {
+                        format!("User {} {} which depicts the last state of the {}{}.\n", user_id, observes, monitor, leaves)
+                    } else if *observed_state_idx == 0 && self.mode == GameMode::Infinite {
+                        let comma = if leaves.is_empty() { "" } else { "," };
+                        format!("User {} {} which depicts the first state of the {}, when the game began{}{}.\n", user_id, observes, monitor, comma, leaves)
+                    } else {
+                        format!("User {} {} which depicts the {}-to-last state of the {}{}.\n", user_id, observes, to_ordinal((states_len - observed_state_idx) as u32), monitor, leaves)
+                    };
+                    if i > 0 {
+                        info2
+                    } else if self.mode == GameMode::Finite {
+                        format!("User {} walks into the {} and is observing the snapshot of the {}...\n\n{}", user_id, room_name, self.monitors_name(&lockers), info2)
+                    } else {
+                        format!("User {} walks into the {} and is browsing the whole history of the {}...\n\n{}", user_id, room_name, self.monitors_name(&lockers), info2)
+                    }
+                }
+            };
+            let observation = Message::Observation {
+                user_id,
+                locker_id: *locker_id,
+                snapshots_ago: states_len - 1 - observed_state_idx,
+                positions: positions.clone(),
+            };
+            events.push(Event::new(observation, info));
+            if shown_state_idx != observed_state_idx && self.config.announce_display_faults {
+                let fault = Message::DisplayFault { user_id, locker_id: *locker_id, snapshots_ago: states_len - 1 - shown_state_idx };
+                let info = format!(
+                    "However, the {} was faulty: it actually showed the {}-to-last state of the {}.\n",
+                    monitor,
+                    to_ordinal((states_len - shown_state_idx) as u32),
+                    self.locker_name(*locker_id)
+                );
+                events.push(Event::new(fault, info));
+            }
+        }
+    }
+
+    // Other users in the hallway may see the user walking into the room, and learn what the user now believes
+    fn witnessing(&mut self, user_id: usize, room: usize, observed: &[Sight], witnesses: &[usize], events: &mut Vec<Event>) {
+        let user = self.state.users.get_by_id(user_id).unwrap();
+        // what the user believes only changed if the room shows their locker
+        let own_observation = observed.iter().find(|sight| sight.locker_id == user.locker_id);
+        let room_name = self.room_name(room);
+        for witness in self.state.users.users.iter_mut() {
+            if !witnesses.contains(&witness.id) {
+                continue;
+            }
+            if let Some(sight) = own_observation {
+                witness.witness(user_id, sight.shown_state_idx, sight.positions.as_deref());
+            }
+            let info = format!("User {} sees User {} walking into the {}.\n", witness.id, user_id, room_name);
+            events.push(Event::new(Message::Witness { witness_id: witness.id, user_id }, info));
+        }
+    }
+
+    /// probe_request asks the player about the state of the game before the prediction
+    fn probe_request(&self) -> Event {
+        let (kind, user_id) = self.state.probe.unwrap();
+        let locker_name = self.locker_name(self.state.users.get_by_id(user_id).unwrap().locker_id);
+        let info1 = match kind {
+            ProbeKind::Control => format!("Before the game goes on, answer a question about User {}.\n", user_id),
+            _ => format!("User {} is coming to Room 1 to take his/her item, but first, answer a question about User {}.\n", self.taking_user_id(), user_id),
+        };
+        let info2 = match kind {
+            ProbeKind::Reality | ProbeKind::Control => formatdoc! {"
+                Where is the item of User {user_id} actually stored in the {locker_name} now?
+                You should only answer the position of the item (e.g. 0 for the 0th, 1 for the 1st, 2 for the 2nd...)."
+            },
+            ProbeKind::Memory => formatdoc! {"
+                Where was the item of User {user_id} stored in the {locker_name} when the game began?
+                You should only answer the position of the item (e.g. 0 for the 0th, 1 for the 1st, 2 for the 2nd...)."
+            },
+            ProbeKind::BeliefRecency => formatdoc! {"
+                Which state of the {locker_name} did User {user_id} see last, either when storing their item or on the monitor?
+                You should only answer how many states before the current one it is (e.g. 0 for the current state, 1 for the state before it...)."
+            },
+            ProbeKind::TrueBelief => formatdoc! {"
+                Does User {user_id} believe the right position of their item?
+                You should only answer 'yes' or 'no'."
+            },
+        };
+        Event::new(Message::ProbeRequest { kind, user_id }, format!("{}\n{}\nPlease answer:", info1, info2))
+    }
+
+    // The player answer the probe, then predict the user's belief
+    fn probing(&mut self, answer: &str, events: &mut Vec<Event>) {
+        let (kind, user_id) = self.state.probe.take().unwrap();
+        let expected = self.probe_answer(kind, user_id);
+        let read = self.config.answer_policy.read(answer, &expected);
+        let correct = read == Some(expected);
+        let score = match self.state.probe_scores.iter_mut().find(|score| score.kind == kind) {
+            Some(score) => score,
+            None => {
+                self.state.probe_scores.push(ProbeScore { kind, asked: 0, correct: 0, unreadable: 0 });
+                self.state.probe_scores.last_mut().unwrap()
+            }
+        };
+        score.asked += 1;
+        score.correct += correct as usize;
+        score.unreadable += read.is_none() as usize;
+        let info = if correct {
+            format!("Your answer is correct! The answer is {}.\n", expected)
+        } else {
+            format!("Your answer is wrong! The answer is {}.\n", expected)
+        };
+        events.push(Event::new(Message::ProbeResult { kind, user_id, correct, expected }, info));
+        self.scene = match kind {
+            ProbeKind::Control => {
+                self.state.last_control = Some(correct);
+                // go on as the Shuffling scene would have
+                if self.rng.gen_bool(1.0 - self.config.transition_probability) { Scene::DecisionMaking } else { Scene::Shuffling }
+            }
+            _ => Scene::Predicting,
+        };
+    }
+
+    /// reprompting tells whether the answer to the pending prediction or probe cannot be read and is asked once more
+    /// instead of being scored, if the config says so
+    fn reprompting(&mut self, answer: &str, events: &mut Vec<Event>) -> bool {
+        let Some(expected) = self.expected_answer_of_scene() else { return false };
+        if self.config.answer_policy.read(answer, &expected).is_some() || !self.config.reprompt_unreadable || self.state.reprompted {
+            self.state.reprompted = false;
+            return false;
+        }
+        self.state.reprompted = true;
+        let reason = match expected {
+            ProbeAnswer::Number(_) => "no number found in the answer",
+            ProbeAnswer::YesNo(_) => "no yes or no found in the answer",
+        };
+        let content = format!("Your answer could not be read ({}), please answer again.\n", reason);
+        events.push(Event::new(Message::InvalidInput { reason: reason.to_string() }, content));
+        true
+    }
+
+    /// probe_answer is the expected answer of a probe about a user
+    fn probe_answer(&self, kind: ProbeKind, user_id: usize) -> ProbeAnswer {
+        let user = self.state.users.get_by_id(user_id).unwrap();
+        let snapshots = &self.state.locker_snapshots[user.locker_id];
+        let inmind_locker_idx = user.inmind_own_locker_state_idx();
+        let real_item_idx = snapshots.last().unwrap().get_item_idx_by_belongs(user_id);
+        match kind {
+            ProbeKind::Reality | ProbeKind::Control => ProbeAnswer::Number(real_item_idx),
+            ProbeKind::Memory => ProbeAnswer::Number(snapshots[0].get_item_idx_by_belongs(user_id)),
+            ProbeKind::BeliefRecency => ProbeAnswer::Number(snapshots.len() - 1 - inmind_locker_idx),
+            ProbeKind::TrueBelief => {
+                let inmind_item_idx = believed_item_idx(snapshots, &user.inmind_position_state_idx[user.locker_id], user_id);
+                ProbeAnswer::YesNo(inmind_item_idx == real_item_idx)
+            }
+        }
+    }
+
+    /// prediction_request asks the player to predict the position the user will go to
+    fn prediction_request(&self) -> Event {
+        let user_id = self.taking_user_id();
+        let locker_id = self.state.users.get_by_id(user_id).unwrap().locker_id;
+        let info1 = if self.config.locker_n > 1 {
+            format!("User {} is coming to Room 1 to take his/her item from the {}...\n", user_id, self.locker_name(locker_id))
+        } else {
+            format!("User {} is coming to Room 1 to take his/her item...\n", user_id)
+        };
+        // ask LLM to make prediction
+        let info2 = match self.state.believer {
+            None => formatdoc! {"
+                You should only answer the position of the item the user will go to retrieve their item (e.g. 0 for the 0th, 1 for the 1st, 2 for the 2nd...).
+                For example, if you think the user will go to position 0th to retrieve their item, you should only answer in single number '0'.
+                Please make your prediction:"
+            },
+            Some(believer_id) => formatdoc! {"
+                You should only answer the position where User {believer_id} thinks User {user_id} will go to retrieve their item (e.g. 0 for the 0th, 1 for the 1st, 2 for the 2nd...).
+                For example, if you think User {believer_id} expects User {user_id} to go to position 0th, you should only answer in single number '0'.
+                Please make your prediction:"
+            },
+        };
+        let request = Message::PredictionRequest {
+            user_id,
+            locker_id,
+            n_positions: self.state.locker_snapshots[locker_id].last().unwrap().items.len(),
+            believer_id: self.state.believer,
+        };
+        Event::new(request, format!("{}\n{}", info1, info2))
+    }
+
+    // The player predict the user's belief
+    fn predicting(&mut self, answer: &str, events: &mut Vec<Event>) {
+        let user_id = self.taking_user_id();
+        let user = self.state.users.get_by_id(user_id).unwrap();
+        let locker_id = user.locker_id;
+        // real item index in the locker
+        let real_item_idx = self.state.locker_snapshots[locker_id].last().unwrap().get_item_idx_by_belongs(user_id);
+        // inmind item index in the locker
+        let inmind_item_idx = believed_item_idx(&self.state.locker_snapshots[locker_id], &user.inmind_position_state_idx[locker_id], user_id);
+
+        let correct = if let Some(believer_id) = self.state.believer.take() {
+            self.second_order_predicting(answer, user_id, believer_id, events)
+        } else {
+            self.first_order_predicting(answer, user_id, events)
+        } as usize;
+        match self.state.last_control {
+            Some(true) => {
+                self.state.control_score.passed_predictions += 1;
+                self.state.control_score.passed_correct += correct;
+            }
+            Some(false) => {
+                self.state.control_score.failed_predictions += 1;
+                self.state.control_score.failed_correct += correct;
+            }
+            None => {}
+        }
+
+        let snapshots = &mut self.state.locker_snapshots[locker_id];
+        let mut locker = snapshots.last().unwrap().clone();
+        locker.exchange_items(real_item_idx, inmind_item_idx);
+        locker.remove_item(inmind_item_idx);
+        snapshots.push(locker);
+        self.state.users.remove_by_id(user_id);
+        self.state.user_decision = Decision::None;
+
+        // tell the LLM the current state of the locker
+        let locker = self.state.locker_snapshots[locker_id].last().unwrap();
+        let info = formatdoc! {"
+            Now, from the {}, you can see the content of the {}:
+            {}
+            ",
+            self.monitor_name(locker_id),
+            self.locker_name(locker_id),
+            describe_locker(locker)
+        };
+        events.push(Event::new(Message::LockerState { locker_id, locker: locker.layout() }, info));
+        // randomly change to one of the following states
+        //1. Shuffling
+        //2. DecisionMaking
+        //3. End (if and only if there is no items left)
+        self.scene = if self.state.users.is_empty() {
+            Scene::End
+        } else {
+            self.next_scene()
+        };
+    }
+
+    /// first_order_predicting scores the prediction of the position the user will go to, and tells whether it is correct
+    fn first_order_predicting(&mut self, answer: &str, user_id: usize, events: &mut Vec<Event>) -> bool {
+        let user = self.state.users.get_by_id(user_id).unwrap();
+        let locker_id = user.locker_id;
+        let snapshots = &self.state.locker_snapshots[locker_id];
+        let real_item_idx = snapshots.last().unwrap().get_item_idx_by_belongs(user_id);
+        let inmind_item_idx = believed_item_idx(snapshots, &user.inmind_position_state_idx[locker_id], user_id);
+        // the snapshot at which the user last saw the position they go to
+        let inmind_locker_idx = user.inmind_position_state_idx[locker_id][inmind_item_idx];
+        let predicted_inmind_item_idx = self.config.answer_policy.read_number(answer);
+        self.state.unreadable += predicted_inmind_item_idx.is_none() as usize;
+        let correct = predicted_inmind_item_idx == Some(inmind_item_idx);
+        let result = Message::PredictionResult { correct, believed_idx: inmind_item_idx, real_idx: real_item_idx };
+        let record = PredictionRecord {
+            turn: self.turns,
+            user_id,
+            locker_id,
+            believer_id: None,
+            believed_snapshot_idx: inmind_locker_idx,
+            snapshots_ago: snapshots.len() - 1 - inmind_locker_idx,
+            believed_idx: inmind_item_idx,
+            real_idx: real_item_idx,
+            true_belief: inmind_item_idx == real_item_idx,
+            answer: answer.to_string(),
+            predicted_idx: predicted_inmind_item_idx,
+            correct,
+        };
+        if correct {
+            let info = format!(
+                "Your prediction is correct! Item in the position {} is exchanged with the correct item in the position {}. User {} successfully retrieved the item from the correct position. You score a point!\n",
+                to_ordinal(inmind_item_idx as u32),
+                to_ordinal(real_item_idx as u32),
+                user_id
+            );
+            events.push(Event::new(result, info));
+            self.state.score += 1;
+        } else {
+            let info = format!(
+                "Your prediction is wrong! The administrator is intervening... Item in the position {} is exchanged with the correct item in the position {}. User {} retrieved the item with the help of the administrator. You score no points.\n",
+                to_ordinal(inmind_item_idx as u32),
+                to_ordinal(real_item_idx as u32),
+                user_id
+            );
+            events.push(Event::new(result, info));
+        }
+        events.push(Event::new(Message::Annotation(record), String::new()));
+        correct
+    }
+
+    /// second_order_predicting scores the prediction of the position the believer thinks the user will go to, and
+    /// tells whether it is correct
+    fn second_order_predicting(&mut self, answer: &str, user_id: usize, believer_id: usize, events: &mut Vec<Event>) -> bool {
+        let locker_id = self.state.users.get_by_id(user_id).unwrap().locker_id;
+        let snapshots = &self.state.locker_snapshots[locker_id];
+        let real_item_idx = snapshots.last().unwrap().get_item_idx_by_belongs(user_id);
+        let believer = self.state.users.get_by_id(believer_id).unwrap();
+        let believed_item_idx = believed_item_idx(snapshots, &believer.others_inmind_position_state_idx[user_id], user_id);
+        let believed_locker_idx = believer.others_inmind_position_state_idx[user_id][believed_item_idx];
+        let predicted_item_idx = self.config.answer_policy.read_number(answer);
+        self.state.unreadable += predicted_item_idx.is_none() as usize;
+        let correct = predicted_item_idx == Some(believed_item_idx);
+        let result = Message::PredictionResult { correct, believed_idx: believed_item_idx, real_idx: real_item_idx };
+        let record = PredictionRecord {
+            turn: self.turns,
+            user_id,
+            locker_id,
+            believer_id: Some(believer_id),
+            believed_snapshot_idx: believed_locker_idx,
+            snapshots_ago: snapshots.len() - 1 - believed_locker_idx,
+            believed_idx: believed_item_idx,
+            real_idx: real_item_idx,
+            true_belief: believed_item_idx == real_item_idx,
+            answer: answer.to_string(),
+            predicted_idx: predicted_item_idx,
+            correct,
+        };
+        let info = if correct {
+            self.state.score += 1;
+            format!(
+                "Your prediction is correct! User {} thinks User {} will go to the position {}. You score a point!\n",
+                believer_id,
+                user_id,
+                to_ordinal(believed_item_idx as u32)
+            )
+        } else {
+            format!(
+                "Your prediction is wrong! User {} thinks User {} will go to the position {}. You score no points.\n",
+                believer_id,
+                user_id,
+                to_ordinal(believed_item_idx as u32)
+            )
+        };
+        events.push(Event::new(result, info));
+        events.push(Event::new(Message::Annotation(record), String::new()));
+        correct
+    }
+
+    /// next_scene randomly chooses between the Shuffling and the DecisionMaking scene
+    fn next_scene(&mut self) -> Scene {
+        if self.rng.gen_bool(self.config.transition_probability) {
+            Scene::Shuffling
+        } else {
+            Scene::DecisionMaking
+        }
+    }
+
+    /// statistics tells the final result of the turn
+    fn statistics(&self) -> Event {
+        let correct = self.state.score;
+        let score = correct * 100 / self.config.user_n;
+        let mut statistics = formatdoc! {"
+            Correct: {}
+            Final score: {}
+            ",
+            correct,
+            score
+        };
+        let unreadable = self.state.unreadable;
+        if unreadable > 0 {
+            statistics.push_str(&format!("Unreadable answers: {}\n", unreadable));
+        }
+        let mut probes = self.state.probe_scores.clone();
+        probes.sort_by_key(|score| score.kind);
+        let mut probe_statistics = String::new();
+        for probe in probes.iter() {
+            probe_statistics.push_str(&format!("{} probes: {}/{} correct", probe.kind.name(), probe.correct, probe.asked));
+            if probe.unreadable > 0 {
+                probe_statistics.push_str(&format!(", {} unreadable", probe.unreadable));
+            }
+            probe_statistics.push('\n');
+        }
+        let control = self.state.last_control.map(|_| self.state.control_score.clone());
+        if let Some(control) = &control {
+            probe_statistics.push_str(&format!(
+                "Correct predictions after a passed control probe: {}/{}\nCorrect predictions after a failed control probe: {}/{}\n",
+                control.passed_correct, control.passed_predictions, control.failed_correct, control.failed_predictions
+            ));
+        }
+        let info = formatdoc! {"
+            {}{}
+            Game Over!
+            ", statistics, probe_statistics};
+        Event::new(Message::GameOver { correct, score, probes, control, unreadable }, info)
+    }
+
+    /// is_last_turn tells whether the current turn is the last one allowed by the turn limit
+    fn is_last_turn(&self) -> bool {
+        self.config.turn_limit.is_some_and(|limit| self.turns + 1 >= limit)
+    }
+
+    // tell the final result, and game over
+    fn end(&mut self, answer: &str) {
+        match answer.trim() {
+            "N" | "n" => {
+                self.over = true;
+            }
+            _ => {
+                self.turns += 1;
+                self.state = Game::init_state(&self.config, &mut self.rng);
+                self.scene = Scene::Init;
+            }
+        }
+    }
+
+    fn taking_user_id(&self) -> usize {
+        match self.state.user_decision {
+            Decision::TakeItem { from } => from,
+            _ => panic!("Invalid decision"),
+        }
+    }
+
+    fn shuffle_event(&self, locker_id: usize) -> Event {
+        let last_snapshot = self.state.locker_snapshots[locker_id].last().unwrap();
+        let locker = self.locker_name(locker_id);
+        let info = formatdoc! {"
+            The {locker} is malfunctioning and randomly resetting the positions of the items in the {locker}...
+            The {locker} has returned to normal.
+            From the {}, you can see the content of the {locker}:
+            {}
+            ",
+            self.monitor_name(locker_id),
+            describe_locker(last_snapshot)
+        };
+        Event::new(Message::Shuffle { locker_id, locker: last_snapshot.layout() }, info)
+    }
+
+    /// locker_name names the locker in the narration, simply "locker" if the game has a single one
+    fn locker_name(&self, locker_id: usize) -> String {
+        if self.config.locker_n > 1 { format!("Locker {}", locker_id) } else { "locker".to_string() }
+    }
+
+    /// monitor_name names the monitor of the locker in the narration, simply "monitor" if the game has a single one
+    fn monitor_name(&self, locker_id: usize) -> String {
+        if self.config.locker_n > 1 { format!("monitor of the Locker {}", locker_id) } else { "monitor".to_string() }
+    }
+
+    /// monitors_name names the monitors of the lockers in the narration
+    fn monitors_name(&self, lockers: &[usize]) -> String {
+        match lockers {
+            [locker_id] => self.monitor_name(*locker_id),
+            _ => format!("monitors of the {}", lockers.iter().map(|&locker_id| self.locker_name(locker_id)).collect::<Vec<_>>().join(" and the ")),
+        }
+    }
+
+    /// room_name names an observation room: users observe the monitor in the Room 2 in Zero and SecondOrder modes,
+    /// and the snapshots in the Room 3 otherwise, the other observation rooms following
+    fn room_name(&self, room: usize) -> String {
+        let first = match self.mode {
+            GameMode::Zero | GameMode::SecondOrder => 2,
+            GameMode::Finite | GameMode::Infinite => 3,
+        };
+        format!("Room {}", first + room)
+    }
+}
+
+/// describe_positions names the positions of a locker, such as "positions 0th and 2nd"
+fn describe_positions(positions: &[usize]) -> String {
+    let ordinals: Vec<String> = positions.iter().map(|&position| to_ordinal(position as u32)).collect();
+    match ordinals.as_slice() {
+        [ordinal] => format!("position {}", ordinal),
+        [init @ .., last] => format!("positions {} and {}", init.join(", "), last),
+        [] => "no position".to_string(),
+    }
+}
+
+/// describe_locker lists the content of each position of the locker, one position per line
+fn describe_locker(locker: &Locker) -> String {
+    let mut s = String::new();
+    for (i, item) in locker.items.iter().enumerate() {
+        if let Some(item) = item {
+            s.push_str(&format!("The position {} stores the item of User {}.\n", to_ordinal(i as u32), item.belongs_to as u32));
+        } else {
+            s.push_str(&format!("The position {} box is empty.\n", to_ordinal(i as u32)));
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::answer::AnswerPolicy;
+    use crate::logic::config::UserProfile;
+    use crate::logic::scenario::{ScenarioSpec, Script};
+
+    /// play runs one turn of the game, answering every prediction with the given answer
+    fn play(game: &mut Game, answer: &str) -> Vec<Event> {
+        // a new turn begins once the player has answered the question of the previous one
+        let mut transcript = game.step(Some(Action::Answer("Y".to_string())));
+        while !matches!(transcript.last().unwrap().message, Message::PlayAgain | Message::GameOver { .. }) {
+            transcript.extend(game.step(Some(Action::Answer(answer.to_string()))));
+        }
+        transcript
+    }
+
+    #[test]
+    fn test_game_runs_without_io() {
+        let mut game = Game::new(GameMode::Finite, GameConfig::default(), 1);
+        let transcript = play(&mut game, "0");
+        assert!(transcript.first().unwrap().content().starts_with("Welcome to, Pick the Right Stuff!"));
+        let predictions = transcript.iter().filter(|event| event.content().contains("Please make your prediction:")).count();
+        assert_eq!(predictions, 5);
+        let results = transcript.iter().filter(|event| matches!(event.message, Message::PredictionResult { .. })).count();
+        assert_eq!(results, 5);
+        for event in transcript.iter() {
+            if let Message::Annotation(record) = &event.message {
+                assert_eq!(record.correct, record.believed_idx == 0);
+                assert_eq!(record.true_belief, record.believed_idx == record.real_idx);
+            }
+        }
+        assert!(transcript.iter().filter(|event| event.require_input()).count() == predictions + 1);
+
+        let events = game.step(Some(Action::Answer("n".to_string())));
+        assert!(events.is_empty());
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn test_annotations_match_the_state() {
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite] {
+            for seed in 0..5 {
+                let mut game = Game::new(mode, GameConfig::default(), seed);
+                let mut events = game.step(None);
+                let mut expected = None;
+                let mut annotations = 0;
+                while !game.is_over() {
+                    for event in &events {
+                        match &event.message {
+                            Message::PredictionRequest { user_id, .. } => {
+                                // the user goes to where their item was in the last snapshot they saw
+                                let user = game.state.users.get_by_id(*user_id).unwrap();
+                                let snapshots = &game.state.locker_snapshots[user.locker_id];
+                                let seen_idx = user.inmind_own_locker_state_idx();
+                                let believed_idx = snapshots[seen_idx].get_item_idx_by_belongs(*user_id);
+                                let real_idx = snapshots.last().unwrap().get_item_idx_by_belongs(*user_id);
+                                expected = Some((*user_id, seen_idx, snapshots.len() - 1 - seen_idx, believed_idx, real_idx));
                             }
-                            _ => {
-                                break;
+                            Message::Annotation(record) => {
+                                let actual = (record.user_id, record.believed_snapshot_idx, record.snapshots_ago, record.believed_idx, record.real_idx);
+                                assert_eq!(Some(actual), expected.take());
+                                assert_eq!(record.true_belief, record.believed_idx == record.real_idx);
+                                annotations += 1;
                             }
+                            _ => {}
                         }
                     }
+                    let answer = if matches!(events.last().unwrap().message, Message::PlayAgain) { "n" } else { "0" };
+                    events = game.step(Some(Action::Answer(answer.to_string())));
+                }
+                assert_eq!(annotations, GameConfig::default().user_n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prompt_is_repeated_without_input() {
+        let mut game = Game::new(GameMode::Zero, GameConfig::default(), 1);
+        let first = game.step(None);
+        let again = game.step(None);
+        assert_eq!(first.last(), again.last());
+        assert_eq!(again.len(), 1);
+    }
+
+    #[test]
+    fn test_turn_limit() {
+        let config = GameConfig { user_n: 2, turn_limit: Some(2), ..Default::default() };
+        let mut game = Game::new(GameMode::Zero, config, 1);
+        let first = play(&mut game, "0");
+        assert!(first.last().unwrap().require_input());
+        let second = play(&mut game, "0");
+        assert!(!second.last().unwrap().require_input());
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn test_infinite_mode() {
+        let mut game = Game::new(GameMode::Infinite, GameConfig::default(), 1);
+        let transcript = play(&mut game, "0");
+        assert!(transcript.first().unwrap().content().contains("the whole history of the monitor"));
+        assert!(transcript.iter().any(|event| event.content().contains("is browsing the whole history of the monitor")));
+    }
+
+    #[test]
+    fn test_user_profiles() {
+        // only user 1 ever observes the monitor
+        let profiles = vec![UserProfile::default(), UserProfile { observe_weight: Some(5.0), ..Default::default() }];
+        let config = GameConfig { user_n: 3, observe_weight: 0.0, profiles, ..Default::default() };
+        let mut game = Game::new(GameMode::Finite, config, 1);
+        let mut observers = vec![];
+        for _ in 0..5 {
+            for event in play(&mut game, "0") {
+                if let Message::Observation { user_id, .. } = event.message {
+                    observers.push(user_id);
+                }
+            }
+        }
+        assert!(!observers.is_empty());
+        assert!(observers.iter().all(|&user_id| user_id == 1));
+    }
+
+    #[test]
+    fn test_probes() {
+        let config = GameConfig { probe_probability: 1.0, ..Default::default() };
+        let mut game = Game::new(GameMode::Finite, config, 1);
+        let transcript = play(&mut game, "0");
+        let mut asked = vec![];
+        for (i, event) in transcript.iter().enumerate() {
+            if let Message::ProbeRequest { kind, user_id } = event.message {
+                // the probe is answered before the prediction
+                let Message::ProbeResult { kind: k, user_id: u, correct, expected } = transcript[i + 1].message else { panic!() };
+                assert_eq!((k, u), (kind, user_id));
+                assert_eq!(correct, expected.matches("0"));
+                assert!(matches!(transcript[i + 2].message, Message::PredictionRequest { .. }));
+                asked.push((kind, correct));
+            }
+        }
+        assert_eq!(asked.len(), 5);
+        let Message::GameOver { correct, probes, .. } = &transcript[transcript.len() - 2].message else { panic!() };
+        let results = transcript.iter().filter(|event| matches!(event.message, Message::PredictionResult { correct: true, .. })).count();
+        assert_eq!(*correct, results);
+        for probe in probes {
+            assert_eq!(probe.asked, asked.iter().filter(|(kind, _)| *kind == probe.kind).count());
+            assert_eq!(probe.correct, asked.iter().filter(|(kind, correct)| *kind == probe.kind && *correct).count());
+        }
+    }
+
+    #[test]
+    fn test_unreadable_answers() {
+        // every unreadable answer is asked once more, then counted apart from the wrong ones
+        let config = GameConfig { probe_probability: 1.0, reprompt_unreadable: true, ..Default::default() };
+        let transcript = play(&mut Game::new(GameMode::Finite, config, 1), "maybe");
+        let reprompts = transcript.iter().filter(|event| matches!(event.message, Message::InvalidInput { .. })).count();
+        let results = transcript.iter().filter(|event| matches!(event.message, Message::PredictionResult { .. } | Message::ProbeResult { .. })).count();
+        assert_eq!(reprompts, results);
+        let Message::GameOver { unreadable, probes, .. } = &transcript[transcript.len() - 2].message else { panic!() };
+        assert_eq!(*unreadable, 5);
+        assert!(probes.iter().all(|probe| probe.unreadable == probe.asked));
+
+        // lenient answers are read, strict ones are not
+        for (answer_policy, expected) in [(AnswerPolicy::Strict, 5), (AnswerPolicy::Lenient, 0)] {
+            let config = GameConfig { probe_probability: 1.0, probe_kinds: vec![ProbeKind::Reality], answer_policy, ..Default::default() };
+            let transcript = play(&mut Game::new(GameMode::Finite, config, 1), "position 0");
+            assert!(!transcript.iter().any(|event| matches!(event.message, Message::InvalidInput { .. })));
+            let Message::GameOver { unreadable, probes, .. } = &transcript[transcript.len() - 2].message else { panic!() };
+            assert_eq!(*unreadable, expected);
+            assert_eq!(probes[0].unreadable, expected);
+        }
+    }
+
+    #[test]
+    fn test_control_probes() {
+        let config = GameConfig { control_probability: 1.0, ..Default::default() };
+        let mut game = Game::new(GameMode::Zero, config, 1);
+        let transcript = play(&mut game, "0");
+        let mut last_control = None;
+        let mut control = ControlScore::default();
+        for (i, event) in transcript.iter().enumerate() {
+            match event.message {
+                // every shuffle is followed by a control probe
+                Message::Shuffle { .. } => assert!(matches!(transcript[i + 1].message, Message::ProbeRequest { kind: ProbeKind::Control, .. })),
+                Message::ProbeResult { kind: ProbeKind::Control, correct, .. } => last_control = Some(correct),
+                Message::PredictionResult { correct, .. } => match last_control {
+                    Some(true) => {
+                        control.passed_predictions += 1;
+                        control.passed_correct += correct as usize;
+                    }
+                    Some(false) => {
+                        control.failed_predictions += 1;
+                        control.failed_correct += correct as usize;
+                    }
+                    None => {}
+                },
+                _ => {}
+            }
+        }
+        assert!(last_control.is_some());
+        let Message::GameOver { control: Some(score), .. } = &transcript[transcript.len() - 2].message else { panic!() };
+        assert_eq!(*score, control);
+    }
+
+    #[test]
+    fn test_second_order_mode() {
+        // without witnesses, users keep thinking the others believe the initial layout
+        let config = GameConfig { witness_probability: 0.0, second_order_probability: 1.0, ..Default::default() };
+        let mut game = Game::new(GameMode::SecondOrder, config, 1);
+        let mut second_order = 0;
+        for _ in 0..3 {
+            for event in play(&mut game, "0") {
+                assert!(!matches!(event.message, Message::Witness { .. }));
+                if let Message::Annotation(record) = event.message {
+                    if record.believer_id.is_some() {
+                        second_order += 1;
+                        assert_eq!(record.believed_snapshot_idx, 0);
+                    }
                 }
             }
         }
-    })
+        // every user but the last one of a turn has another user to be asked about
+        assert_eq!(second_order, 3 * 4);
+
+        // the other users see every observation, right after it
+        let config = GameConfig { witness_probability: 1.0, ..Default::default() };
+        let mut game = Game::new(GameMode::SecondOrder, config, 1);
+        let transcript = play(&mut game, "0");
+        assert!(transcript.iter().any(|event| matches!(event.message, Message::Witness { .. })));
+        for (i, event) in transcript.iter().enumerate() {
+            if let Message::Witness { user_id, .. } = event.message {
+                assert!(matches!(transcript[i - 1].message, Message::Observation { user_id: id, .. } | Message::Witness { user_id: id, .. } if id == user_id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiple_lockers() {
+        let config = GameConfig { user_n: 6, locker_n: 3, rooms: vec![vec![0, 1], vec![2]], ..Default::default() };
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite] {
+            let mut game = Game::new(mode, config.clone(), 1);
+            let transcript = play(&mut game, "0");
+            let Message::Intro { lockers, .. } = &transcript[0].message else { panic!() };
+            assert_eq!(lockers.iter().map(|locker| locker.len()).collect::<Vec<_>>(), vec![2, 2, 2]);
+            for (i, event) in transcript.iter().enumerate() {
+                match &event.message {
+                    Message::Annotation(record) => assert_eq!(record.locker_id, record.user_id % 3),
+                    // a room shows either the Locker 0 and the Locker 1, or the Locker 2
+                    Message::Observation { user_id, locker_id: 0, .. } => {
+                        assert!(matches!(transcript[i + 1].message, Message::Observation { user_id: u, locker_id: 1, .. } if u == *user_id));
+                    }
+                    Message::Observation { locker_id, .. } => assert!(*locker_id == 1 || *locker_id == 2),
+                    _ => {}
+                }
+            }
+            assert_eq!(game.snapshots().len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_partial_observations() {
+        let config = GameConfig { partial_observation: Some(PartialObservation::Random(2)), ..Default::default() };
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+            let mut game = Game::new(mode, config.clone(), 3);
+            let transcript = play(&mut game, "0");
+            // the player is told where a user goes when none of the positions they remember holds their item
+            assert!(transcript[0].content().contains("they go to the position they have seen the least recently"));
+            for event in transcript {
+                match &event.message {
+                    Message::Observation { positions, .. } => assert_eq!(positions.as_ref().unwrap().len(), 2),
+                    Message::Annotation(record) => assert_eq!(record.true_belief, record.believed_idx == record.real_idx),
+                    _ => {}
+                }
+            }
+        }
+        // a user only looks at the position of their item, in the locker storing it
+        let config = GameConfig { user_n: 6, locker_n: 2, partial_observation: Some(PartialObservation::OwnItem), ..Default::default() };
+        let mut game = Game::new(GameMode::Zero, config, 3);
+        for event in play(&mut game, "0") {
+            if let Message::Observation { user_id, locker_id, positions, .. } = &event.message {
+                assert_eq!(*locker_id, user_id % 2);
+                assert_eq!(positions.as_ref().unwrap().len(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_faults() {
+        let config = GameConfig { display_fault_probability: 1.0, ..Default::default() };
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+            let mut faults = 0;
+            for seed in 0..5 {
+                let transcript = play(&mut Game::new(mode, config.clone(), seed), "0");
+                for (i, event) in transcript.iter().enumerate() {
+                    if let Message::DisplayFault { user_id, snapshots_ago, .. } = &event.message {
+                        // the monitor shows an older snapshot than the one told
+                        let Message::Observation { user_id: observer_id, snapshots_ago: told, .. } = &transcript[i - 1].message else { panic!() };
+                        assert_eq!(user_id, observer_id);
+                        assert!(snapshots_ago > told);
+                        faults += 1;
+                    }
+                }
+            }
+            assert!(faults > 0);
+        }
+        // the player is not told, the game is the same otherwise
+        let announced = play(&mut Game::new(GameMode::Zero, config.clone(), 3), "0");
+        let config = GameConfig { announce_display_faults: false, ..config };
+        let unannounced = play(&mut Game::new(GameMode::Zero, config, 3), "0");
+        let announced: Vec<_> = announced.into_iter().filter(|event| !matches!(event.message, Message::DisplayFault { .. })).collect();
+        assert_eq!(announced, unannounced);
+    }
+
+    #[test]
+    fn test_scenario() {
+        let scenario = ScenarioSpec { true_belief_n: 2, false_belief_n: 4, staleness: 2, distractor_n: 3 };
+        let configs = [
+            (GameConfig { user_n: 6, locker_n: 2, scenario: Some(scenario), ..Default::default() }, 4),
+            // every user observes in the room showing their locker
+            (GameConfig { user_n: 4, locker_n: 2, rooms: vec![vec![0], vec![1]], scenario: Some(ScenarioSpec { false_belief_n: 4, ..Default::default() }), ..Default::default() }, 4),
+            (GameConfig { user_n: 4, locker_n: 2, rooms: vec![vec![1], vec![0]], scenario: Some(ScenarioSpec { true_belief_n: 4, ..Default::default() }), ..Default::default() }, 0),
+        ];
+        for (config, false_belief_n) in configs {
+            let staleness = config.scenario.as_ref().unwrap().staleness;
+            for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+                for seed in 0..5 {
+                    let mut game = Game::new(mode, config.clone(), seed);
+                    let records: Vec<PredictionRecord> = play(&mut game, "0")
+                        .into_iter()
+                        .filter_map(|event| match event.message {
+                            Message::Annotation(record) => Some(record),
+                            _ => None,
+                        })
+                        .collect();
+                    assert_eq!(records.len(), config.user_n);
+                    let false_beliefs: Vec<&PredictionRecord> = records.iter().filter(|record| !record.true_belief).collect();
+                    assert_eq!(false_beliefs.len(), false_belief_n);
+                    assert!(false_beliefs.iter().all(|record| record.snapshots_ago == staleness));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_script() {
+        let script: Script = serde_yaml::from_str(include_str!("../../scenarios/sally_anne.yaml")).unwrap();
+        let config = GameConfig { user_n: 2, script: Some(script), ..Default::default() };
+        for seed in 0..3 {
+            let transcript = play(&mut Game::new(GameMode::Zero, config.clone(), seed), "0");
+            let Message::Intro { lockers, .. } = &transcript[0].message else { panic!() };
+            assert_eq!(lockers, &vec![vec![Some(0), Some(1), None]]);
+            let records: Vec<(usize, usize, usize)> = transcript
+                .iter()
+                .filter_map(|event| match &event.message {
+                    Message::Annotation(record) => Some((record.user_id, record.believed_idx, record.real_idx)),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(records, vec![(0, 0, 2), (1, 1, 1)]);
+            let probes: Vec<ProbeAnswer> = transcript
+                .iter()
+                .filter_map(|event| match &event.message {
+                    Message::ProbeResult { expected, .. } => Some(*expected),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(probes, vec![ProbeAnswer::Number(2), ProbeAnswer::YesNo(true)]);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_transcript() {
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+            let mut a = Game::new(mode, GameConfig::default(), 7);
+            let mut b = Game::new(mode, GameConfig::default(), 7);
+            for _ in 0..3 {
+                assert_eq!(play(&mut a, "1"), play(&mut b, "1"));
+            }
+            let mut c = Game::new(mode, GameConfig::default(), 7);
+            let mut d = Game::new(mode, GameConfig::default(), 8);
+            assert_ne!(play(&mut c, "1"), play(&mut d, "1"));
+        }
+    }
 }
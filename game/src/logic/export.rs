@@ -0,0 +1,136 @@
+use std::io::Write;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
+
+use super::config::GameConfig;
+use super::engine::{Action, Game, GameMode};
+use super::message::{Message, PredictionRecord, ProbeAnswer};
+
+/// Episode is a turn of the game written down with the right answers, to be played without a server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Episode {
+    pub episode: usize,
+    pub mode: GameMode,
+    /// seed of the session whose first turn is the episode
+    pub seed: u64,
+    /// the conversation, as the research player builds it: one message per line sent by the game, and the right
+    /// answer to every prediction and probe
+    pub messages: Vec<ChatMessage>,
+    pub questions: Vec<Question>,
+}
+
+/// ChatMessage is a message of the conversation with the player.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+/// Role is the side of the conversation a message comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// The instructions given to the player before the game
+    System,
+    /// The game
+    User,
+    /// The player
+    Assistant,
+}
+
+/// Question is a prediction or a probe asked in an episode.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Question {
+    /// index of the message asking the question among the messages of the episode
+    pub message_idx: usize,
+    /// the `PredictionRequest` or the `ProbeRequest`
+    pub request: Message,
+    /// the right answer: the position for predictions, a number or yes or no for probes
+    pub expected: ProbeAnswer,
+    /// the ground truth of the prediction, for predictions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<PredictionRecord>,
+}
+
+/// episode plays the first turn of a session with the right answers and writes it down
+pub fn episode(mode: GameMode, config: GameConfig, seed: u64, episode: usize) -> Episode {
+    let mut game = Game::new(mode, config, seed);
+    let mut messages = vec![];
+    let mut questions: Vec<Question> = vec![];
+    let mut answer = None;
+    while !game.is_over() {
+        let events = game.step(answer.take());
+        for event in events {
+            match event.message {
+                Message::PlayAgain => {
+                    return Episode { episode, mode, seed, messages, questions };
+                }
+                Message::Annotation(record) => {
+                    if let Some(question) = questions.last_mut() {
+                        question.record = Some(record);
+                    }
+                }
+                Message::PredictionRequest { .. } | Message::ProbeRequest { .. } => {
+                    let expected = game.expected_answer().expect("the game asks a question");
+                    messages.push(ChatMessage { role: Role::User, content: event.content });
+                    questions.push(Question { message_idx: messages.len() - 1, request: event.message, expected, record: None });
+                    let content = expected.to_string();
+                    messages.push(ChatMessage { role: Role::Assistant, content: content.clone() });
+                    answer = Some(Action::Answer(content));
+                }
+                _ => messages.push(ChatMessage { role: Role::User, content: event.content }),
+            }
+        }
+    }
+    Episode { episode, mode, seed, messages, questions }
+}
+
+/// export writes `n` episodes as JSON lines, the seed of each episode being drawn from `seed`
+pub fn export(mode: GameMode, config: &GameConfig, seed: u64, n: usize, mut writer: impl Write) -> Result<(), GameError> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for i in 0..n {
+        let episode = episode(mode, config.clone(), rng.gen(), i);
+        writeln!(writer, "{}", serde_json::to_string(&episode)?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_episode_answers_are_right() {
+        let config = GameConfig { probe_probability: 0.5, ..Default::default() };
+        for mode in [GameMode::Zero, GameMode::Finite, GameMode::Infinite, GameMode::SecondOrder] {
+            let episode = episode(mode, config.clone(), 5, 0);
+            assert_eq!(episode.messages[0].role, Role::User);
+            assert!(episode.messages[0].content.contains("Game Begins!"));
+            assert!(episode.messages.last().unwrap().content.contains("Game Over!"));
+            assert!(episode.questions.iter().any(|question| matches!(question.request, Message::ProbeRequest { .. })));
+            let predictions: Vec<&PredictionRecord> = episode.questions.iter().filter_map(|question| question.record.as_ref()).collect();
+            assert_eq!(predictions.len(), config.user_n);
+            assert!(predictions.iter().all(|record| record.correct));
+            for question in episode.questions {
+                assert_eq!(episode.messages[question.message_idx + 1].role, Role::Assistant);
+                assert!(question.expected.matches(&episode.messages[question.message_idx + 1].content));
+            }
+        }
+    }
+
+    #[test]
+    fn test_export_is_deterministic() {
+        let mut a = vec![];
+        let mut b = vec![];
+        export(GameMode::Finite, &GameConfig::default(), 3, 4, &mut a).unwrap();
+        export(GameMode::Finite, &GameConfig::default(), 3, 4, &mut b).unwrap();
+        assert_eq!(a, b);
+        let lines: Vec<Episode> = String::from_utf8(a).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.iter().map(|episode| episode.episode).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    }
+}
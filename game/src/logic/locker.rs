@@ -10,6 +10,13 @@ impl Locker {
             items: (0..items_n).map(|_| Some(Item::new(0))).collect(),
         }
     }
+    /// Create a locker laid out as given: the user whose item is stored at each position, if any
+    pub fn from_layout(layout: &[Option<usize>]) -> Locker {
+        Locker {
+            items: layout.iter().map(|owner| owner.map(Item::new)).collect(),
+        }
+    }
+
     /// Exchange the items at the given indices
     pub fn exchange_items(&mut self, idx1: usize, idx2: usize) {
         self.items.swap(idx1, idx2)
@@ -20,6 +27,16 @@ impl Locker {
         self.items[idx].take()
     }
 
+    /// Get the layout of the locker: the id of the user each item belongs to, position by position
+    pub fn layout(&self) -> Vec<Option<usize>> {
+        self.items.iter().map(|item| item.as_ref().map(|item| item.belongs_to)).collect()
+    }
+
+    /// Rearrange the items as in the given layout, which is expected to hold the same items
+    pub fn rearrange(&mut self, layout: &[Option<usize>]) {
+        self.items = layout.iter().map(|owner| owner.map(Item::new)).collect();
+    }
+
     /// Get Item Idx by item belongs id
     pub fn get_item_idx_by_belongs(&self, belongs: usize) -> usize {
         self.items
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+
+use super::answer::AnswerPolicy;
+use super::engine::GameMode;
+
+/// Message is the structured counterpart of what the game tells the player.
+///
+/// Lockers are described by their layout: for each position, the id of the user whose item is stored there,
+/// if any. Lockers are identified by their index, 0 if the game has a single locker.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Message {
+    /// The game instruction and the initial layout of every locker
+    Intro { n_users: usize, lockers: Vec<Vec<Option<usize>>> },
+    /// The content of the locker after a user retrieved their item
+    LockerState {
+        #[serde(default)]
+        locker_id: usize,
+        locker: Vec<Option<usize>>,
+    },
+    /// The locker malfunctioned and shuffled the items
+    Shuffle {
+        #[serde(default)]
+        locker_id: usize,
+        locker: Vec<Option<usize>>,
+    },
+    /// A user observed the snapshot of the monitor of the locker taken `snapshots_ago` snapshots before the last one,
+    /// whole or only at the given positions
+    Observation {
+        user_id: usize,
+        #[serde(default)]
+        locker_id: usize,
+        snapshots_ago: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        positions: Option<Vec<usize>>,
+    },
+    /// The monitor was faulty when the user observed it: it actually showed the snapshot taken `snapshots_ago`
+    /// snapshots before the last one
+    DisplayFault { user_id: usize, locker_id: usize, snapshots_ago: usize },
+    /// The player must predict the position the user will go to, or, if `believer_id` is given, the position
+    /// the believer thinks the user will go to, in the locker storing the item of the user
+    PredictionRequest {
+        user_id: usize,
+        #[serde(default)]
+        locker_id: usize,
+        n_positions: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        believer_id: Option<usize>,
+    },
+    /// A user saw another user walking into the room of the monitor
+    Witness { witness_id: usize, user_id: usize },
+    /// The result of the prediction, with the position the user believed and the real one
+    PredictionResult { correct: bool, believed_idx: usize, real_idx: usize },
+    /// The player must answer a probe about the state of the game rather than a belief
+    ProbeRequest { kind: ProbeKind, user_id: usize },
+    /// The result of the probe, with the expected answer
+    ProbeResult { kind: ProbeKind, user_id: usize, correct: bool, expected: ProbeAnswer },
+    /// The result of the turn, `correct` predictions out of the users and the score in percent, along with the
+    /// results of the probes, which do not count in the score
+    GameOver {
+        correct: usize,
+        score: usize,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        probes: Vec<ProbeScore>,
+        /// accuracy of the predictions depending on the last control probe, if any was asked
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        control: Option<ControlScore>,
+        /// predictions whose answer could not be read, among the wrong ones
+        #[serde(default, skip_serializing_if = "is_zero")]
+        unreadable: usize,
+    },
+    /// The player must tell whether to play another turn
+    PlayAgain,
+    /// The last input of the player could not be read, or the answer it holds, the pending prompt follows again
+    InvalidInput { reason: String },
+    /// The ground truth of the last prediction, for evaluation only: its content is empty
+    Annotation(PredictionRecord),
+}
+
+impl Message {
+    /// require_input tells whether the player must answer the message
+    pub fn require_input(&self) -> bool {
+        matches!(self, Message::PredictionRequest { .. } | Message::ProbeRequest { .. } | Message::PlayAgain)
+    }
+}
+
+/// PredictionRecord is the ground truth of a prediction round, along with the answer of the player.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PredictionRecord {
+    /// turn of the session, starting from 0
+    pub turn: usize,
+    pub user_id: usize,
+    /// the locker storing the item of the user
+    #[serde(default)]
+    pub locker_id: usize,
+    /// the user whose belief about the user was asked, for second-order predictions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub believer_id: Option<usize>,
+    /// index of the snapshot of the locker the user believes, or the believer thinks the user believes, among the
+    /// snapshots of the locker; with partial observations, the snapshot at which the believed position was last seen
+    pub believed_snapshot_idx: usize,
+    /// how many snapshots before the last one the user believes, 0 if the user knows the last one
+    pub snapshots_ago: usize,
+    /// position of the item the user believes
+    pub believed_idx: usize,
+    /// position of the item in reality
+    pub real_idx: usize,
+    /// whether the belief of the user equals the reality
+    pub true_belief: bool,
+    /// answer of the player, as received
+    pub answer: String,
+    /// position predicted by the player, if the answer could be read with the answer policy of the game
+    pub predicted_idx: Option<usize>,
+    pub correct: bool,
+}
+
+/// ProbeKind is the type of a probe, asking about what happened rather than about what a user believes.
+///
+/// Probes tell tracking failures apart from theory of mind failures: a player who cannot tell where an item is
+/// cannot tell where a user believes it is either.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// Where the item of the user is actually stored, answered with a position
+    Reality,
+    /// Where the item of the user was stored at the beginning of the turn, answered with a position
+    Memory,
+    /// How many snapshots before the last one the user saw last, answered with a number
+    BeliefRecency,
+    /// Whether the user believes the right position of their item, answered with yes or no
+    TrueBelief,
+    /// Where the item of the user is right after a shuffle, answered with a position
+    Control,
+}
+
+impl ProbeKind {
+    /// ALL are the kinds of the probes asked before a prediction, control probes are asked after shuffles
+    pub const ALL: [ProbeKind; 4] = [ProbeKind::Reality, ProbeKind::Memory, ProbeKind::BeliefRecency, ProbeKind::TrueBelief];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeKind::Reality => "Reality",
+            ProbeKind::Memory => "Memory",
+            ProbeKind::BeliefRecency => "Belief recency",
+            ProbeKind::TrueBelief => "True belief",
+            ProbeKind::Control => "Control",
+        }
+    }
+}
+
+/// ProbeAnswer is the expected answer of a probe.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ProbeAnswer {
+    Number(usize),
+    YesNo(bool),
+}
+
+impl ProbeAnswer {
+    /// matches tells whether the answer of the player is the expected one, read strictly
+    pub fn matches(&self, answer: &str) -> bool {
+        AnswerPolicy::Strict.read(answer, self) == Some(*self)
+    }
+}
+
+impl std::fmt::Display for ProbeAnswer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeAnswer::Number(n) => write!(f, "{}", n),
+            ProbeAnswer::YesNo(true) => write!(f, "yes"),
+            ProbeAnswer::YesNo(false) => write!(f, "no"),
+        }
+    }
+}
+
+/// ProbeScore is the number of probes of a kind asked in a turn and how many were answered correctly.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ProbeScore {
+    pub kind: ProbeKind,
+    pub asked: usize,
+    pub correct: usize,
+    /// probes whose answer could not be read, among the wrong ones
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub unreadable: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// ControlScore is the accuracy of the predictions made while the last control probe of the turn was passed,
+/// and while it was failed. Predictions made before any control probe are not counted.
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ControlScore {
+    pub passed_predictions: usize,
+    pub passed_correct: usize,
+    pub failed_predictions: usize,
+    pub failed_correct: usize,
+}
+
+/// PROTOCOL_VERSION is the version of the messages exchanged between the server and the client
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// CAPABILITY_MESSAGES is the capability of a client to receive the structured `Message` of every content
+pub const CAPABILITY_MESSAGES: &str = "messages";
+
+/// CAPABILITY_ANNOTATIONS is the capability of a client to receive the `PredictionRecord` of every prediction
+pub const CAPABILITY_ANNOTATIONS: &str = "annotations";
+
+/// Hello is the first line a client sends, to open the handshake.
+///
+/// A client that does not send it is served the legacy protocol: the content only, without the messages.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub client_name: String,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// seed of the session requested by the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+/// Welcome is the answer of the server to `Hello`, describing the session.
+///
+/// If the protocol versions differ, the server closes the connection right after it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub struct Welcome {
+    pub protocol_version: u32,
+    pub server_version: String,
+    pub mode: GameMode,
+    pub session_id: u64,
+    pub seed: u64,
+    /// capabilities of the client that the server accepted
+    pub capabilities: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_answer_matches() {
+        assert!(ProbeAnswer::Number(2).matches(" 2\n"));
+        assert!(!ProbeAnswer::Number(2).matches("two"));
+        assert!(ProbeAnswer::YesNo(true).matches("Yes"));
+        assert!(ProbeAnswer::YesNo(false).matches("no"));
+        assert!(!ProbeAnswer::YesNo(false).matches("maybe"));
+    }
+}
@@ -1,3 +1,12 @@
 pub mod locker;
+pub mod config;
 pub mod engine;
+pub mod message;
 pub mod user;
+pub mod session;
+pub mod replay;
+pub mod scenario;
+pub mod export;
+pub mod bot;
+pub mod bench;
+pub mod answer;
@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use crate::error::GameError;
+use crate::utils::record::Entry;
+use crate::utils::tcp::Data;
+
+use super::session::Session;
+
+/// Replay is the result of playing a recorded session again with the same seed and answers.
+#[derive(Debug, PartialEq)]
+pub struct Replay {
+    pub session_id: u64,
+    /// number of lines sent to the player in the recorded session
+    pub expected_n: usize,
+    /// number of lines sent to the player in the replayed session
+    pub actual_n: usize,
+    /// number of lines that differ between both sessions
+    pub mismatch_n: usize,
+    /// the first line that differs, if any
+    pub first_mismatch: Option<Mismatch>,
+    /// final score of every turn of the replayed session
+    pub scores: Vec<usize>,
+}
+
+/// Mismatch is a line sent to the player that differs between the recorded and the replayed session.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    /// index of the line among the lines sent to the player
+    pub idx: usize,
+    pub expected: Option<Data>,
+    pub actual: Option<Data>,
+}
+
+impl Replay {
+    /// is_identical tells whether the replayed session sent exactly the recorded lines
+    pub fn is_identical(&self) -> bool {
+        self.mismatch_n == 0
+    }
+}
+
+/// replay reads the transcript written by `serve --record` and plays it again
+pub fn replay(path: &Path) -> Result<Replay, GameError> {
+    let content = std::fs::read_to_string(path)?;
+    let entries = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Entry>, _>>()?;
+    replay_entries(&entries)
+}
+
+/// replay_entries plays the session of a transcript again: the game is seeded with the recorded seed, fed with
+/// the recorded answers in order, and every line it sends is compared to the recorded one.
+///
+/// The predictions and snapshots of the transcript are ignored, they are derived from the game.
+pub fn replay_entries(entries: &[Entry]) -> Result<Replay, GameError> {
+    let Some(Entry::Session { session_id, mode, seed, config, capabilities, .. }) = entries.first() else {
+        return Err(GameError::MalformedInput("the transcript does not start with a session entry".to_string()));
+    };
+    let mut expected = vec![];
+    let mut answers = vec![];
+    for entry in entries {
+        match entry {
+            Entry::Sent { data, .. } => expected.push(data.clone()),
+            Entry::Received { line, .. } => answers.push(line.as_bytes()),
+            _ => {}
+        }
+    }
+
+    let mut session = Session::new(*mode, config.as_ref().clone(), *seed, capabilities);
+    let mut actual = session.advance(None);
+    for answer in answers {
+        if session.is_over() {
+            break;
+        }
+        actual.extend(session.advance(Some(answer)));
+    }
+
+    let mut mismatch_n = 0;
+    let mut first_mismatch = None;
+    for idx in 0..expected.len().max(actual.len()) {
+        let (expected, actual) = (expected.get(idx), actual.get(idx));
+        if expected != actual {
+            mismatch_n += 1;
+            first_mismatch.get_or_insert(Mismatch { idx, expected: expected.cloned(), actual: actual.cloned() });
+        }
+    }
+    Ok(Replay {
+        session_id: *session_id,
+        expected_n: expected.len(),
+        actual_n: actual.len(),
+        mismatch_n,
+        first_mismatch,
+        scores: session.scores().to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::config::GameConfig;
+    use crate::logic::engine::GameMode;
+    use crate::logic::session::record_session;
+
+    /// record records a turn of a session served with `start`, and reads the entries of its transcript
+    fn record(name: &str, seed: u64) -> Vec<Entry> {
+        let dir = std::env::temp_dir().join(format!("game-replay-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = record_session(&dir, GameMode::Finite, GameConfig::default(), seed, 1);
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        content.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_replay_is_identical() {
+        let replay = replay_entries(&record("identical", 7)).unwrap();
+        assert!(replay.is_identical());
+        assert_eq!(replay.scores.len(), 1);
+    }
+
+    #[test]
+    fn test_tampered_transcript_is_detected() {
+        let mut entries = record("tampered", 7);
+        let idx = entries.iter().rposition(|entry| matches!(entry, Entry::Sent { .. })).unwrap();
+        entries[idx] = Entry::Sent { timestamp: 0, data: Data::new(false, "Tampered".to_string()) };
+        let replay = replay_entries(&entries).unwrap();
+        assert_eq!(replay.mismatch_n, 1);
+        assert_eq!(replay.first_mismatch.unwrap().actual.unwrap().content(), "Do you want to play another turn?(Y/n)\n");
+    }
+
+    #[test]
+    fn test_transcript_without_session() {
+        assert!(replay_entries(&[]).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::shuffle::shuffle;
+
+use super::config::{ConfigError, GameConfig};
+use super::engine::GameMode;
+use super::message::ProbeKind;
+
+/// Step is an event of a scripted turn. Scripted steps happen exactly as written: observations always succeed and
+/// show the monitor faithfully. Probes are still asked as configured.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Step {
+    /// The locker malfunctions and its items are rearranged as in `locker`
+    Shuffle {
+        #[serde(default)]
+        locker_id: usize,
+        locker: Vec<Option<usize>>,
+    },
+    /// The user walks into an observation room and sees the snapshot taken `snapshots_ago` snapshots before the last
+    /// one of every locker shown, whole or only at the given positions, while the witnesses see them walking in
+    Observe {
+        user_id: usize,
+        #[serde(default)]
+        room: usize,
+        #[serde(default)]
+        snapshots_ago: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        positions: Option<Vec<usize>>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        witnesses: Vec<usize>,
+    },
+    /// The user comes to take their item, the player predicts where they go, or where the believer thinks they go,
+    /// after answering a probe about the user if one is given
+    Take {
+        user_id: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        believer_id: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        probe: Option<ProbeKind>,
+    },
+}
+
+/// ScenarioSpec describes the turns the scenario generator builds, in place of the random walk of the scenes.
+///
+/// Every user observes their locker before coming to take their item, as in the Sally–Anne test: their belief is
+/// true if their item stays in place until they take it, and false if their locker is shuffled `staleness` times
+/// after they looked, moving their item. Distracting shuffles either happen before a user looks, or shuffle their
+/// locker while leaving their item in place.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScenarioSpec {
+    /// number of predictions where the user believes the right position, per turn
+    pub true_belief_n: usize,
+    /// number of predictions where the user believes a wrong position, per turn
+    pub false_belief_n: usize,
+    /// how many snapshots before the last one a user holding a false belief saw their locker
+    pub staleness: usize,
+    /// number of shuffles which do not change what the users believe, per turn
+    pub distractor_n: usize,
+}
+
+impl Default for ScenarioSpec {
+    fn default() -> Self {
+        ScenarioSpec {
+            true_belief_n: 0,
+            false_belief_n: 0,
+            staleness: 1,
+            distractor_n: 0,
+        }
+    }
+}
+
+impl ScenarioSpec {
+    /// validate checks that the turns can be built for the given lockers, of the given sizes, shown in the given
+    /// observation rooms
+    pub fn validate(&self, user_n: usize, locker_sizes: &[usize], rooms: &[Vec<usize>]) -> Result<(), ConfigError> {
+        if self.true_belief_n + self.false_belief_n != user_n {
+            return Err(ConfigError::Invalid(format!(
+                "the scenario must make as many predictions as users, got {} true and {} false beliefs for {} users",
+                self.true_belief_n, self.false_belief_n, user_n
+            )));
+        }
+        if self.false_belief_n > 0 && self.staleness == 0 {
+            return Err(ConfigError::Invalid("the staleness of the scenario must be at least 1 for false beliefs".to_string()));
+        }
+        // an item can only be moved away from where the user saw it if the locker has another position
+        if self.false_belief_n > 0 && locker_sizes.iter().any(|&size| size < 2) {
+            return Err(ConfigError::Invalid("every locker must have at least 2 positions for false beliefs".to_string()));
+        }
+        // every user observes their own locker
+        if let Some(locker_id) = (0..locker_sizes.len()).find(|&locker_id| locker_sizes[locker_id] > 0 && !rooms.iter().any(|room| room.contains(&locker_id))) {
+            return Err(ConfigError::Invalid(format!("the Locker {} must be shown in an observation room for the scenario", locker_id)));
+        }
+        Ok(())
+    }
+
+    /// generate builds the steps of a turn, given the initial layout of every locker, the locker of every user and
+    /// the lockers shown in every observation room. A user observes in the first room showing their locker.
+    pub fn generate(&self, lockers: &[Vec<Option<usize>>], user_lockers: &[usize], rooms: &[Vec<usize>], rng: &mut impl Rng) -> Vec<Step> {
+        let mut lockers = lockers.to_vec();
+        let mut order: Vec<usize> = (0..user_lockers.len()).collect();
+        order.shuffle(rng);
+        let mut false_belief = vec![false; order.len()];
+        for &user_id in order.choose_multiple(rng, self.false_belief_n) {
+            false_belief[user_id] = true;
+        }
+        let mut distractors = vec![0; order.len()];
+        for _ in 0..self.distractor_n {
+            distractors[rng.gen_range(0..order.len())] += 1;
+        }
+
+        let mut steps = vec![];
+        for user_id in order {
+            let locker_id = user_lockers[user_id];
+            // a user holding a false belief is only distracted before they look
+            let after = if false_belief[user_id] { 0 } else { (0..distractors[user_id]).filter(|_| rng.gen_bool(0.5)).count() };
+            for _ in after..distractors[user_id] {
+                let locker_id = rng.gen_range(0..lockers.len());
+                shuffle(&mut lockers[locker_id], rng);
+                steps.push(Step::Shuffle { locker_id, locker: lockers[locker_id].clone() });
+            }
+            let room = rooms.iter().position(|room| room.contains(&locker_id)).expect("the locker is shown in a room");
+            steps.push(Step::Observe { user_id, room, snapshots_ago: 0, positions: None, witnesses: vec![] });
+            let seen_idx = position_of(&lockers[locker_id], user_id);
+            if false_belief[user_id] {
+                for i in 0..self.staleness {
+                    let locker = &mut lockers[locker_id];
+                    shuffle(locker, rng);
+                    // the last shuffle leaves the item away from where the user saw it
+                    while i + 1 == self.staleness && position_of(locker, user_id) == seen_idx {
+                        locker.shuffle(rng);
+                    }
+                    steps.push(Step::Shuffle { locker_id, locker: locker.clone() });
+                }
+            } else {
+                for _ in 0..after {
+                    // every position but the one of the item is shuffled
+                    let locker = &mut lockers[locker_id];
+                    let mut others: Vec<usize> = (0..locker.len()).filter(|&idx| idx != seen_idx).collect();
+                    let contents: Vec<Option<usize>> = others.iter().map(|&idx| locker[idx]).collect();
+                    shuffle(&mut others, rng);
+                    for (idx, content) in others.into_iter().zip(contents) {
+                        locker[idx] = content;
+                    }
+                    steps.push(Step::Shuffle { locker_id, locker: locker.clone() });
+                }
+            }
+            steps.push(Step::Take { user_id, believer_id: None, probe: None });
+            // as in the game, the item at the believed position is exchanged with the item of the user, then removed
+            let locker = &mut lockers[locker_id];
+            let real_idx = position_of(locker, user_id);
+            locker.swap(real_idx, seen_idx);
+            locker[seen_idx] = None;
+        }
+        steps
+    }
+}
+
+/// Script is a turn written by hand: the initial layout of every locker and the steps to play, every turn.
+///
+/// The users whose items are stored in a locker only care about this locker. The steps play the whole turn: every
+/// user takes their item, so that nothing is left to chance but the probes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// the user whose item is stored at each position of each locker, indexed by the id of the locker
+    pub lockers: Vec<Vec<Option<usize>>>,
+    pub steps: Vec<Step>,
+}
+
+impl Script {
+    /// load reads the script from a YAML or JSON file, depending on its extension
+    pub fn load(path: &Path) -> Result<Script, ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::Read(e.to_string()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string())),
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string())),
+            _ => Err(ConfigError::Read(format!("unsupported script file {}, expected .yaml or .json", path.display()))),
+        }
+    }
+
+    /// user_n is the number of users storing an item in the lockers
+    pub fn user_n(&self) -> usize {
+        self.lockers.iter().flatten().flatten().count()
+    }
+
+    /// user_lockers is the locker storing the item of each user, indexed by the id of the user
+    pub fn user_lockers(&self) -> Vec<usize> {
+        let mut user_lockers = vec![0; self.user_n()];
+        for (locker_id, locker) in self.lockers.iter().enumerate() {
+            for &user_id in locker.iter().flatten() {
+                user_lockers[user_id] = locker_id;
+            }
+        }
+        user_lockers
+    }
+
+    /// validate checks that every step of the script can be played in the given mode, with the given config, and that
+    /// every user takes their item by the end of the script
+    pub fn validate(&self, mode: GameMode, config: &GameConfig) -> Result<(), ConfigError> {
+        let invalid = |step: usize, reason: String| Err(ConfigError::Invalid(format!("step {} of the script: {}", step, reason)));
+        let user_n = self.user_n();
+        let mut owners: Vec<usize> = self.lockers.iter().flatten().flatten().copied().collect();
+        owners.sort();
+        if owners != (0..user_n).collect::<Vec<_>>() {
+            return Err(ConfigError::Invalid("the lockers of the script must store one item for each user, from User 0 on".to_string()));
+        }
+        if user_n != config.user_n || self.lockers.len() != config.locker_n {
+            return Err(ConfigError::Invalid(format!(
+                "the script has {} users and {} lockers, the config {} users and {} lockers",
+                user_n,
+                self.lockers.len(),
+                config.user_n,
+                config.locker_n
+            )));
+        }
+        let rooms = config.observation_rooms();
+        let user_lockers = self.user_lockers();
+        // what the game will be like at each step: the users who have taken their item and the snapshots of the lockers
+        let mut taken = vec![false; user_n];
+        let mut states_len = vec![1; self.lockers.len()];
+        let present = |user_id: usize, taken: &[bool]| user_id < user_n && !taken[user_id];
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                Step::Shuffle { locker_id, locker } => {
+                    if *locker_id >= self.lockers.len() {
+                        return invalid(i, format!("there is no Locker {}", locker_id));
+                    }
+                    let mut expected: Vec<usize> = self.lockers[*locker_id].iter().flatten().filter(|&&user_id| !taken[user_id]).copied().collect();
+                    let mut actual: Vec<usize> = locker.iter().flatten().copied().collect();
+                    expected.sort();
+                    actual.sort();
+                    if locker.len() != self.lockers[*locker_id].len() || actual != expected {
+                        return invalid(i, format!("the shuffled Locker {} must hold the items of users {:?}", locker_id, expected));
+                    }
+                    states_len[*locker_id] += 1;
+                }
+                Step::Observe { user_id, room, snapshots_ago, positions, witnesses } => {
+                    if !present(*user_id, &taken) {
+                        return invalid(i, format!("User {} is not waiting", user_id));
+                    }
+                    let Some(lockers) = rooms.get(*room) else {
+                        return invalid(i, format!("there is no observation room {}", room));
+                    };
+                    if *snapshots_ago > 0 && matches!(mode, GameMode::Zero | GameMode::SecondOrder) {
+                        return invalid(i, "the monitor only shows the last snapshot in this mode".to_string());
+                    }
+                    for &locker_id in lockers {
+                        if *snapshots_ago >= states_len[locker_id] {
+                            return invalid(i, format!("the Locker {} has only {} snapshots", locker_id, states_len[locker_id]));
+                        }
+                        if positions.iter().flatten().any(|&position| position >= self.lockers[locker_id].len()) {
+                            return invalid(i, format!("the Locker {} has only {} positions", locker_id, self.lockers[locker_id].len()));
+                        }
+                    }
+                    if !witnesses.is_empty() && mode != GameMode::SecondOrder {
+                        return invalid(i, "users only witness observations in SecondOrder mode".to_string());
+                    }
+                    if let Some(witness_id) = witnesses.iter().find(|&&witness_id| witness_id == *user_id || !present(witness_id, &taken)) {
+                        return invalid(i, format!("User {} cannot witness User {} observing", witness_id, user_id));
+                    }
+                }
+                Step::Take { user_id, believer_id, probe } => {
+                    if !present(*user_id, &taken) {
+                        return invalid(i, format!("User {} is not waiting", user_id));
+                    }
+                    if let Some(believer_id) = believer_id {
+                        if mode != GameMode::SecondOrder {
+                            return invalid(i, "second-order predictions are only asked in SecondOrder mode".to_string());
+                        }
+                        if believer_id == user_id || !present(*believer_id, &taken) {
+                            return invalid(i, format!("User {} cannot believe where User {} will go", believer_id, user_id));
+                        }
+                    }
+                    if *probe == Some(ProbeKind::Control) {
+                        return invalid(i, "control probes are asked with control_probability".to_string());
+                    }
+                    taken[*user_id] = true;
+                    states_len[user_lockers[*user_id]] += 1;
+                }
+            }
+        }
+        if let Some(user_id) = taken.iter().position(|&taken| !taken) {
+            return Err(ConfigError::Invalid(format!("the script must play the whole turn, User {} never takes their item", user_id)));
+        }
+        Ok(())
+    }
+}
+
+fn position_of(locker: &[Option<usize>], user_id: usize) -> usize {
+    locker.iter().position(|&owner| owner == Some(user_id)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_generate() {
+        let spec = ScenarioSpec { true_belief_n: 2, false_belief_n: 2, staleness: 3, distractor_n: 4 };
+        let lockers = vec![vec![Some(1), Some(3), Some(0), Some(2)]];
+        let steps = spec.generate(&lockers, &[0, 0, 0, 0], &[vec![0]], &mut StdRng::seed_from_u64(1));
+        let count = |f: fn(&Step) -> bool| steps.iter().filter(|step| f(step)).count();
+        assert_eq!(count(|step| matches!(step, Step::Take { .. })), 4);
+        assert_eq!(count(|step| matches!(step, Step::Observe { .. })), 4);
+        assert_eq!(count(|step| matches!(step, Step::Shuffle { .. })), 2 * 3 + 4);
+    }
+
+    #[test]
+    fn test_impossible_scenarios() {
+        let rooms = [vec![0, 1]];
+        assert!(ScenarioSpec { true_belief_n: 2, ..Default::default() }.validate(3, &[3], &[vec![0]]).is_err());
+        assert!(ScenarioSpec { false_belief_n: 3, staleness: 0, ..Default::default() }.validate(3, &[3], &[vec![0]]).is_err());
+        assert!(ScenarioSpec { false_belief_n: 3, ..Default::default() }.validate(3, &[2, 1], &rooms).is_err());
+        assert!(ScenarioSpec { true_belief_n: 3, ..Default::default() }.validate(3, &[2, 1], &rooms).is_ok());
+        // the users of Locker 1 could never see it
+        assert!(ScenarioSpec { true_belief_n: 3, ..Default::default() }.validate(3, &[2, 1], &[vec![0]]).is_err());
+        assert!(ScenarioSpec { true_belief_n: 3, ..Default::default() }.validate(3, &[2, 1, 0], &[vec![1], vec![0]]).is_ok());
+    }
+
+    fn script_config(script: &Script) -> GameConfig {
+        GameConfig { user_n: script.user_n(), locker_n: script.lockers.len(), script: Some(script.clone()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_sally_anne_script() {
+        let script: Script = serde_yaml::from_str(include_str!("../../scenarios/sally_anne.yaml")).unwrap();
+        assert_eq!(script.user_n(), 2);
+        assert_eq!(script.user_lockers(), vec![0, 0]);
+        let config = script_config(&script);
+        assert_eq!(script.validate(GameMode::Zero, &config), Ok(()));
+    }
+
+    #[test]
+    fn test_impossible_scripts() {
+        let script: Script = serde_yaml::from_str(include_str!("../../scenarios/sally_anne.yaml")).unwrap();
+        let config = script_config(&script);
+        let invalid = [
+            // the item of User 1 is lost
+            vec![Step::Shuffle { locker_id: 0, locker: vec![Some(0), None, None] }],
+            vec![Step::Shuffle { locker_id: 1, locker: vec![Some(0), Some(1), None] }],
+            vec![Step::Take { user_id: 0, believer_id: None, probe: None }, Step::Take { user_id: 0, believer_id: None, probe: None }],
+            vec![Step::Take { user_id: 0, believer_id: Some(1), probe: None }],
+            vec![Step::Observe { user_id: 0, room: 0, snapshots_ago: 1, positions: None, witnesses: vec![] }],
+            vec![Step::Observe { user_id: 0, room: 0, snapshots_ago: 0, positions: Some(vec![3]), witnesses: vec![] }],
+            vec![Step::Observe { user_id: 0, room: 0, snapshots_ago: 0, positions: None, witnesses: vec![1] }],
+        ];
+        for steps in invalid {
+            let script = Script { steps, ..script.clone() };
+            assert!(matches!(script.validate(GameMode::Zero, &config), Err(ConfigError::Invalid(reason)) if reason.starts_with("step ")));
+        }
+        // User 1 would go on at random
+        let mut steps = script.steps.clone();
+        steps.pop();
+        assert!(Script { steps, ..script.clone() }.validate(GameMode::Zero, &config).is_err());
+        let script = Script { lockers: vec![vec![Some(0), Some(2)]], ..script };
+        assert!(script.validate(GameMode::Zero, &config).is_err());
+    }
+
+    #[test]
+    fn test_parse_step() {
+        let step: Step = serde_json::from_str(r#"{"type": "observe", "user_id": 1}"#).unwrap();
+        assert_eq!(step, Step::Observe { user_id: 1, room: 0, snapshots_ago: 0, positions: None, witnesses: vec![] });
+        assert!(serde_json::from_str::<Step>(r#"{"type": "take", "user_id": 1, "foo": 2}"#).is_err());
+        // a shuffle tells how it rearranges the locker
+        assert!(serde_json::from_str::<Step>(r#"{"type": "shuffle", "locker_id": 0}"#).is_err());
+    }
+}
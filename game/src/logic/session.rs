@@ -0,0 +1,326 @@
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::error::GameError;
+use crate::utils::record::{timestamp, Entry, Recorder};
+use crate::utils::tcp::{Data, read_until_separator, write_line, write_to_stream};
+
+use super::config::GameConfig;
+use super::engine::{Action, Event, Game, GameMode};
+use super::message::{Hello, Message, PredictionRecord, Welcome, CAPABILITY_ANNOTATIONS, CAPABILITY_MESSAGES, PROTOCOL_VERSION};
+
+/// HELLO_TIMEOUT is how long the server waits for the `Hello` of the client before serving the legacy protocol
+const HELLO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Session is the protocol of a session with a player, on top of a `Game`, without any I/O.
+///
+/// It turns the events of the game into the lines sent to the player, depending on the capabilities of the
+/// client, and the lines received from the player into the actions of the game.
+pub struct Session {
+    game: Game,
+    seed: u64,
+    structured: bool,
+    annotations: bool,
+    first: bool,
+    predictions: Vec<PredictionRecord>,
+    scores: Vec<usize>,
+}
+
+impl Session {
+    pub fn new(mode: GameMode, config: GameConfig, seed: u64, capabilities: &[String]) -> Session {
+        Session {
+            game: Game::new(mode, config, seed),
+            seed,
+            structured: capabilities.iter().any(|c| c == CAPABILITY_MESSAGES),
+            annotations: capabilities.iter().any(|c| c == CAPABILITY_ANNOTATIONS),
+            first: true,
+            predictions: vec![],
+            scores: vec![],
+        }
+    }
+
+    /// advance feeds the line received from the player, none at the beginning of the session, and returns the
+    /// lines to send to the player. A malformed line is reported and the pending prompt is sent again.
+    pub fn advance(&mut self, line: Option<&[u8]>) -> Vec<Data> {
+        let mut lines = vec![];
+        let input = match line.map(parse_answer) {
+            None => None,
+            Some(Ok(answer)) => Some(Action::Answer(answer)),
+            Some(Err(reason)) => {
+                let content = format!("Your answer could not be read ({}), please answer again.\n", reason);
+                let mut data = Data::new(false, content);
+                if self.structured {
+                    data = data.with_message(Message::InvalidInput { reason });
+                }
+                lines.push(data);
+                None
+            }
+        };
+        let mut events = self.game.step(input).into_iter().peekable();
+        while let Some(event) = events.next() {
+            if let Message::GameOver { score, .. } = event.message {
+                self.scores.push(score);
+            }
+            if let Message::Annotation(record) = &event.message {
+                self.predictions.push(record.clone());
+                if self.annotations {
+                    lines.push(Data::new(false, event.content).with_message(event.message));
+                }
+                continue;
+            }
+            let mut data = if self.structured {
+                Data::new(event.require_input(), event.content).with_message(event.message)
+            } else if matches!(event.message, Message::GameOver { .. }) && matches!(events.peek(), Some(Event { message: Message::PlayAgain, .. })) {
+                // legacy clients expect the result of the turn and the question to play again in one prompt
+                let play_again = events.next().unwrap();
+                Data::new(true, event.content + &play_again.content)
+            } else {
+                Data::new(event.require_input(), event.content)
+            };
+            if self.first {
+                data = data.with_seed(self.seed);
+                self.first = false;
+            }
+            lines.push(data);
+        }
+        lines
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// take_predictions returns the ground truth of the predictions made since the last call
+    pub fn take_predictions(&mut self) -> Vec<PredictionRecord> {
+        std::mem::take(&mut self.predictions)
+    }
+
+    /// scores are the final scores of the turns played so far
+    pub fn scores(&self) -> &[usize] {
+        &self.scores
+    }
+}
+
+/// parse_answer reads the answer of the player from a line
+fn parse_answer(line: &[u8]) -> Result<String, String> {
+    let answer = std::str::from_utf8(line).map_err(|e| e.to_string())?;
+    let answer = Data::from_json(answer).map_err(|e| match e {
+        GameError::MalformedInput(reason) => reason,
+        e => e.to_string(),
+    })?;
+    Ok(answer.content().to_string())
+}
+
+/// start returns the TCP handler of the game, which drives a `Session` over the stream of a player
+///
+/// Every session is seeded with the seed requested by the client, or with `seed`, or with a random seed if
+/// none is given. The seed is reported to the player in the `Welcome` and along with the first message, so
+/// that the session can be replayed.
+///
+/// The ground truth of every prediction is logged, and sent to clients with the annotations capability.
+/// If `record` is given, the transcript of every session is written in this directory.
+pub fn start(mode: GameMode, config: GameConfig, seed: Option<u64>, record: Option<PathBuf>) -> Box<dyn Fn(TcpStream) -> Result<(), GameError> + Send + Sync> {
+    Box::new(move |mut stream: TcpStream| {
+        let session_id: u64 = rand::random();
+        let hello = read_hello(&mut stream);
+        let seed = hello.as_ref().and_then(|hello| hello.seed).or(seed).unwrap_or_else(rand::random);
+        let mut capabilities = vec![];
+        if let Some(hello) = hello {
+            capabilities = hello.capabilities.into_iter()
+                .filter(|c| c == CAPABILITY_MESSAGES || c == CAPABILITY_ANNOTATIONS)
+                .collect();
+            let welcome = Welcome {
+                protocol_version: PROTOCOL_VERSION,
+                server_version: env!("CARGO_PKG_VERSION").to_string(),
+                mode,
+                session_id,
+                seed,
+                capabilities: capabilities.clone(),
+            };
+            println!("Client {} joined session {}", hello.client_name, session_id);
+            write_line(&mut stream, &serde_json::to_string(&welcome)?)?;
+            if hello.protocol_version != PROTOCOL_VERSION {
+                return Err(GameError::Protocol(format!(
+                    "incompatible protocol version {} of client {}",
+                    hello.protocol_version, hello.client_name
+                )));
+            }
+        }
+        println!("Session seed: {}", seed);
+
+        let mut recorder = None;
+        if let Some(dir) = &record {
+            let mut r = Recorder::create(dir, session_id)?;
+            r.record(&Entry::Session {
+                timestamp: timestamp(),
+                session_id,
+                server_version: env!("CARGO_PKG_VERSION").to_string(),
+                mode,
+                seed,
+                config: Box::new(config.clone()),
+                capabilities: capabilities.clone(),
+            })?;
+            recorder = Some(r);
+        }
+
+        let mut session = Session::new(mode, config.clone(), seed, &capabilities);
+        let mut line = None;
+        loop {
+            let lines = session.advance(line.as_deref());
+            for record in session.take_predictions() {
+                println!("Prediction of session with seed {}: {}", seed, serde_json::to_string(&record)?);
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&Entry::Prediction { timestamp: timestamp(), record })?;
+                }
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record_snapshots(session.game())?;
+            }
+            for data in lines {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&Entry::Sent { timestamp: timestamp(), data: data.clone() })?;
+                }
+                write_to_stream(&mut stream, data)?;
+            }
+            if session.is_over() {
+                return Ok(());
+            }
+            // get the answer from the player
+            let received = read_until_separator(&mut stream)?;
+            if let Some(recorder) = recorder.as_mut() {
+                let line = String::from_utf8_lossy(&received).to_string();
+                recorder.record(&Entry::Received { timestamp: timestamp(), line })?;
+            }
+            line = Some(received);
+        }
+    })
+}
+
+/// read_hello waits for the `Hello` of the client, legacy clients do not send any
+fn read_hello(stream: &mut TcpStream) -> Option<Hello> {
+    stream.set_read_timeout(Some(HELLO_TIMEOUT)).ok()?;
+    let mut byte = [0; 1];
+    let ready = matches!(stream.peek(&mut byte), Ok(n) if n > 0);
+    stream.set_read_timeout(None).ok()?;
+    if !ready {
+        return None;
+    }
+    let line = read_until_separator(stream).ok()?;
+    serde_json::from_slice(&line).ok()
+}
+
+/// record_session serves a session with `start`, recorded in the directory, and plays `turns` turns of it with the
+/// random bot, as a client would. It returns the path of the transcript.
+#[cfg(test)]
+pub(crate) fn record_session(dir: &std::path::Path, mode: GameMode, config: GameConfig, seed: u64, turns: usize) -> PathBuf {
+    use super::bench::{play, Table};
+    use super::bot::{Bot, BotKind};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port() as usize;
+    let handler = start(mode, config, Some(seed), Some(dir.to_path_buf()));
+    let server = std::thread::spawn(move || handler(listener.accept().unwrap().0));
+    let (stream, welcome) = crate::utils::tcp::connect(port, None, "test".to_string()).unwrap();
+    play(&mut Table::Remote(stream), &mut Bot::new(BotKind::Random, seed), turns).unwrap();
+    server.join().unwrap().unwrap();
+    dir.join(format!("session_{}.jsonl", welcome.session_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// connect serves one session of the game on a local port and connects to it
+    fn connect(seed: Option<u64>) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = start(GameMode::Zero, GameConfig::default(), seed, None);
+        std::thread::spawn(move || handler(listener.accept().unwrap().0).ok());
+        TcpStream::connect(addr).unwrap()
+    }
+
+    fn read_data(stream: &mut TcpStream) -> Data {
+        Data::from_json(&String::from_utf8(read_until_separator(stream).unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut stream = connect(Some(3));
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: "test".to_string(),
+            capabilities: vec![CAPABILITY_MESSAGES.to_string(), CAPABILITY_ANNOTATIONS.to_string(), "unknown".to_string()],
+            seed: Some(42),
+        };
+        write_line(&mut stream, &serde_json::to_string(&hello).unwrap()).unwrap();
+        let welcome: Welcome = serde_json::from_slice(&read_until_separator(&mut stream).unwrap()).unwrap();
+        assert_eq!(welcome.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(welcome.mode, GameMode::Zero);
+        assert_eq!(welcome.seed, 42);
+        assert_eq!(welcome.capabilities, vec![CAPABILITY_MESSAGES.to_string(), CAPABILITY_ANNOTATIONS.to_string()]);
+        let intro = read_data(&mut stream);
+        assert_eq!(intro.seed(), Some(42));
+        assert!(matches!(intro.message(), Some(Message::Intro { .. })));
+        // the ground truth of the prediction follows its result
+        loop {
+            let data = read_data(&mut stream);
+            if data.require_input() {
+                write_to_stream(&mut stream, Data::new(false, "0".to_string())).unwrap();
+            }
+            if let Some(Message::PredictionResult { correct, .. }) = data.message() {
+                let annotation = read_data(&mut stream);
+                assert!(matches!(annotation.message(), Some(Message::Annotation(record)) if record.correct == *correct));
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_incompatible_client_is_closed() {
+        let mut stream = connect(None);
+        let hello = Hello { protocol_version: PROTOCOL_VERSION + 1, client_name: "test".to_string(), capabilities: vec![], seed: None };
+        write_line(&mut stream, &serde_json::to_string(&hello).unwrap()).unwrap();
+        let welcome: Welcome = serde_json::from_slice(&read_until_separator(&mut stream).unwrap()).unwrap();
+        assert_eq!(welcome.protocol_version, PROTOCOL_VERSION);
+        assert!(read_until_separator(&mut stream).is_err());
+    }
+
+    #[test]
+    fn test_malformed_input_is_prompted_again() {
+        let mut stream = connect(Some(3));
+        let mut prompt = read_data(&mut stream);
+        while !prompt.require_input() {
+            prompt = read_data(&mut stream);
+        }
+        write_line(&mut stream, "not json").unwrap();
+        let error = read_data(&mut stream);
+        assert!(error.content().starts_with("Your answer could not be read"));
+        assert_eq!(read_data(&mut stream).content(), prompt.content());
+    }
+
+    #[test]
+    fn test_legacy_client() {
+        let mut stream = connect(Some(3));
+        let intro = read_data(&mut stream);
+        assert_eq!(intro.seed(), Some(3));
+        assert!(intro.message().is_none());
+        // the legacy client finds the result of the turn in the prompt to play again
+        loop {
+            let data = read_data(&mut stream);
+            if data.require_input() && data.content().contains("Game Over!") {
+                assert!(data.content().ends_with("Do you want to play another turn?(Y/n)\n"));
+                break;
+            }
+            if data.require_input() {
+                write_to_stream(&mut stream, Data::new(false, "0".to_string())).unwrap();
+            }
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 
+use super::locker::Locker;
+
 /// Decision is an enum that holds the possible decisions an user can make.
 #[derive(Clone, Copy)]
 pub enum Decision {
@@ -9,31 +12,92 @@ pub enum Decision {
 }
 
 impl Decision {
-    pub fn rand_choose(rng: &mut impl Rng, from: usize) -> Decision {
-        let decision = match rng.gen_range(0..2) {
+    /// rand_choose draws a decision according to the weights of taking the item, observing the monitor and
+    /// doing nothing, in this order
+    pub fn rand_choose(rng: &mut impl Rng, from: usize, weights: [f64; 3]) -> Decision {
+        let weights = WeightedIndex::new(weights).expect("Invalid decision weights");
+        match weights.sample(rng) {
             0 => Decision::TakeItem { from },
             1 => Decision::Observe { from },
             2 => Decision::None,
             _ => panic!("Invalid decision"),
-        };
-        decision
+        }
     }
 }
 
-/// User is a struct that holds the id of the user and the locker layout it has in mind at the latest accessed moment.
+/// User is a struct that holds the id of the user and the layout it has in mind of each locker at the latest accessed
+/// moment.
+///
+/// As a user may only glimpse some positions of a locker, the layout they have in mind is kept position by position:
+/// the snapshot at which they last saw each position.
+///
+/// The user also has in mind the layout every other user has in mind of their own locker, as far as they know: it is
+/// updated when they witness another user observing the monitor.
 #[derive(Clone)]
 pub struct User {
     pub id: usize,
-    pub inmind_locker_state_idx: usize,
+    /// the locker storing the item of the user
+    pub locker_id: usize,
+    /// the last snapshot of each locker the user has seen, indexed by the id of the locker
+    pub inmind_locker_state_idx: Vec<usize>,
+    /// the snapshot at which the user has last seen each position of each locker, indexed by the id of the locker
+    pub inmind_position_state_idx: Vec<Vec<usize>>,
+    /// the snapshot at which the user believes each user has last seen each position of their own locker, indexed by
+    /// the id of the other user
+    pub others_inmind_position_state_idx: Vec<Vec<usize>>,
 }
 
 impl User {
-    pub fn new(id: usize, locker_state_idx: usize) -> User {
+    /// new creates a user who has seen the whole initial snapshot of the lockers, of the given sizes, whose items are
+    /// stored in the given locker and the other users' items in `user_lockers`
+    pub fn new(id: usize, locker_id: usize, locker_sizes: &[usize], user_lockers: &[usize]) -> User {
         User {
-            id: id,
-            inmind_locker_state_idx: locker_state_idx,
+            id,
+            locker_id,
+            inmind_locker_state_idx: vec![0; locker_sizes.len()],
+            inmind_position_state_idx: locker_sizes.iter().map(|&size| vec![0; size]).collect(),
+            others_inmind_position_state_idx: user_lockers.iter().map(|&locker_id| vec![0; locker_sizes[locker_id]]).collect(),
         }
     }
+
+    /// inmind_own_locker_state_idx is the last snapshot the user has seen of the locker storing their item
+    pub fn inmind_own_locker_state_idx(&self) -> usize {
+        self.inmind_locker_state_idx[self.locker_id]
+    }
+
+    /// observe updates the beliefs of the user with a snapshot of a locker, seen whole or at some positions only
+    pub fn observe(&mut self, locker_id: usize, snapshot_idx: usize, positions: Option<&[usize]>) {
+        self.inmind_locker_state_idx[locker_id] = snapshot_idx;
+        update_positions(&mut self.inmind_position_state_idx[locker_id], snapshot_idx, positions);
+    }
+
+    /// witness updates what the user believes another user has seen of their own locker
+    pub fn witness(&mut self, user_id: usize, snapshot_idx: usize, positions: Option<&[usize]>) {
+        update_positions(&mut self.others_inmind_position_state_idx[user_id], snapshot_idx, positions);
+    }
+}
+
+fn update_positions(position_state_idx: &mut [usize], snapshot_idx: usize, positions: Option<&[usize]>) {
+    match positions {
+        None => position_state_idx.fill(snapshot_idx),
+        Some(positions) => positions.iter().for_each(|&position| position_state_idx[position] = snapshot_idx),
+    }
+}
+
+/// believed_item_idx is the position where a user goes to retrieve the item `belongs`, given the snapshot at which
+/// they have last seen each position of its locker.
+///
+/// The user goes where they have seen the item most recently. If they have since seen another content at every position
+/// where they saw it, they go to the position they have seen the least recently, the first one if several.
+pub fn believed_item_idx(snapshots: &[Locker], position_state_idx: &[usize], belongs: usize) -> usize {
+    let seen_there = |position: usize| {
+        snapshots[position_state_idx[position]].items[position].as_ref().is_some_and(|item| item.is_belongs_to(belongs))
+    };
+    let positions = 0..position_state_idx.len();
+    match positions.clone().filter(|&position| seen_there(position)).max_by_key(|&position| position_state_idx[position]) {
+        Some(position) => position,
+        None => positions.min_by_key(|&position| position_state_idx[position]).unwrap(),
+    }
 }
 
 /// UserCollection is a struct that holds a list of users.
@@ -43,9 +107,18 @@ pub struct UserCollection {
 }
 
 impl UserCollection {
-    pub fn new(user_n: usize, locker_state_idx: usize) -> UserCollection {
+    /// new creates the users, storing their items in the lockers in turn
+    pub fn new(user_n: usize, locker_n: usize) -> UserCollection {
+        let user_lockers: Vec<usize> = (0..user_n).map(|i| i % locker_n).collect();
+        let locker_sizes: Vec<usize> = (0..locker_n).map(|locker_id| user_lockers.iter().filter(|&&l| l == locker_id).count()).collect();
+        UserCollection::with_lockers(&user_lockers, &locker_sizes)
+    }
+
+    /// with_lockers creates the users, storing the item of each user in the given locker, among lockers of the given
+    /// sizes
+    pub fn with_lockers(user_lockers: &[usize], locker_sizes: &[usize]) -> UserCollection {
         UserCollection {
-            users: (0..user_n).map(|i| User::new(i, locker_state_idx)).collect(),
+            users: user_lockers.iter().enumerate().map(|(i, &locker_id)| User::new(i, locker_id, locker_sizes, user_lockers)).collect(),
         }
     }
 
@@ -59,6 +132,11 @@ impl UserCollection {
         self.users.iter_mut().find(|user| user.id == id)
     }
 
+    /// Get the user by id (not idx)
+    pub fn get_by_id(&self, id: usize) -> Option<&User> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
     /// Check if the user collection is empty
     pub fn is_empty(&self) -> bool {
         self.users.is_empty()
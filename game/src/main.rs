@@ -1,29 +1,202 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
 use clap::Parser;
-use game::{cli, utils::tcp::{client, server}};
-use game::logic::engine::start;
-use game::logic::engine::GameMode::{Finite, Zero};
+use game::{cli, utils::tcp::{client, connect, server}};
+use game::error::GameError;
+use game::logic::bench::{bench, play, Summary, Table};
+use game::logic::bot::{Bot, BotKind};
+use game::logic::config::GameConfig;
+use game::logic::export::export;
+use game::logic::replay::replay;
+use game::logic::scenario::Script;
+use game::utils::chat::{ChatConfig, ChatPlayer};
+use game::utils::player::{CommandPlayer, Player};
+use game::logic::session::start;
+use game::logic::engine::GameMode::{Finite, Infinite, SecondOrder, Zero};
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), GameError> {
     let cli = cli::Cli::parse();
     match cli.command {
-        Some(cli::Commands::Serve { mode }) => {
-            if mode == "zero" {
-                let server_thread = std::thread::spawn(|| { server(8080, start(Zero)) });
-                println!("Game server is running in Zero Belief History mode!");
-                server_thread.join().expect("Failed to join server thread");
-            } else if mode == "finite" {
-                let server_thread = std::thread::spawn(|| { server(8081, start(Finite)) });
-                println!("Game server is running in Finite Belief History mode!");
-                server_thread.join().expect("Failed to join server thread");
-            } else {
-                println!("Invalid mode, choose either 'zero' or 'finite'!");
+        Some(cli::Commands::Serve { mode, config, seed, record, scenario }) => {
+            let Ok(mode) = mode.parse() else {
+                println!("Invalid mode, choose either 'zero', 'finite', 'infinite' or 'second-order'!");
+                return Ok(());
+            };
+            let (port, name) = match mode {
+                Zero => (8080, "Zero Belief History"),
+                Finite => (8081, "Finite Belief History"),
+                Infinite => (8082, "Infinite Belief History"),
+                SecondOrder => (8083, "Second-Order Belief"),
+            };
+            let mut config = match config {
+                Some(path) => GameConfig::load(&path)?,
+                None => GameConfig::default(),
+            };
+            if let Some(path) = scenario {
+                // the script decides the users and the lockers
+                let script = Script::load(&path)?;
+                config.user_n = script.user_n();
+                config.locker_n = script.lockers.len();
+                config.script = Some(script);
+                config.validate()?;
+            }
+            if let Some(script) = &config.script {
+                script.validate(mode, &config)?;
+            }
+            if let Some(dir) = &record {
+                std::fs::create_dir_all(dir)?;
+            }
+            let server_thread = std::thread::spawn(move || { server(port, start(mode, config, seed, record)) });
+            println!("Game server is running in {} mode!", name);
+            server_thread.join().expect("Failed to join server thread")?;
+        }
+        Some(cli::Commands::Client { port, seed, player: cli::PlayerArgs { bot: Some(bot), .. }, games }) => {
+            let kind: BotKind = match bot.parse() {
+                Ok(kind) => kind,
+                Err(e) => {
+                    println!("{}", e);
+                    return Ok(());
+                }
+            };
+            let (stream, welcome) = connect(port, seed, format!("game-bot-{}/{}", kind.name(), env!("CARGO_PKG_VERSION")))?;
+            let scores = play(&mut Table::Remote(stream), &mut Bot::new(kind, welcome.seed), games)?;
+            for (turn, score) in scores.iter().enumerate() {
+                println!("Turn {}: final score {}", turn, score);
+            }
+            print_distribution(kind.name(), &scores);
+        }
+        Some(cli::Commands::Client { port, seed, player, .. }) => match choose_player(player, seed.unwrap_or(0))? {
+            Some((mut player, name)) => {
+                println!("Game client is running with {}!", name);
+                client(port, seed, Some(player.as_mut()))?;
+            }
+            None => {
+                let client_thread = std::thread::spawn(move || { client(port, seed, None) });
+                println!("Game client is running!");
+                client_thread.join().expect("Failed to join client thread")?;
+            }
+        },
+        Some(cli::Commands::Export { mode, config, seed, n, output }) => {
+            let Ok(mode) = mode.parse() else {
+                println!("Invalid mode, choose either 'zero', 'finite', 'infinite' or 'second-order'!");
+                return Ok(());
+            };
+            let config = match config {
+                Some(path) => GameConfig::load(&path)?,
+                None => GameConfig::default(),
+            };
+            if let Some(script) = &config.script {
+                script.validate(mode, &config)?;
+            }
+            export(mode, &config, seed, n, BufWriter::new(File::create(&output)?))?;
+            println!("{} episodes written to {}", n, output.display());
+        }
+        Some(cli::Commands::Bench { mode, port, config, seed, turns, player, name, output }) => {
+            let mode = match mode.map(|mode| mode.parse()).transpose() {
+                Ok(mode) => mode,
+                Err(_) => {
+                    println!("Invalid mode, choose either 'zero', 'finite', 'infinite' or 'second-order'!");
+                    return Ok(());
+                }
+            };
+            let config = match config {
+                Some(path) => GameConfig::load(&path)?,
+                None => GameConfig::default(),
+            };
+            if let (Some(mode), Some(script)) = (mode, &config.script) {
+                script.validate(mode, &config)?;
             }
+            let Some((mut player, default_name)) = choose_player(player, seed.unwrap_or(0))? else {
+                println!("Choose a player with --bot, --exec or --chat!");
+                return Ok(());
+            };
+            let report = bench(port, mode, config, seed, player.as_mut(), name.unwrap_or(default_name), turns)?;
+            let path = report.write(&output)?;
+            for (turn, score) in report.scores.iter().enumerate() {
+                println!("Turn {}: final score {}", turn, score);
+            }
+            print_distribution(&report.model, &report.scores);
+            println!("Results written to {}", path.display());
         }
-        Some(cli::Commands::Client {port}) => {
-            let client_thread = std::thread::spawn(move || { client(port) });
-            println!("Game client is running!");
-            client_thread.join().expect("Failed to join client thread");
+        Some(cli::Commands::Replay { file, rescore }) => {
+            let replay = replay(&file)?;
+            println!("Session {}: {} lines recorded, {} lines replayed", replay.session_id, replay.expected_n, replay.actual_n);
+            for (turn, score) in replay.scores.iter().enumerate() {
+                println!("Turn {}: final score {}", turn, score);
+            }
+            if let Some(mismatch) = &replay.first_mismatch {
+                println!("{} lines differ, the first one is line {}:", replay.mismatch_n, mismatch.idx);
+                println!("  recorded: {}", mismatch.expected.as_ref().map_or("nothing".to_string(), |data| data.to_json()));
+                println!("  replayed: {}", mismatch.actual.as_ref().map_or("nothing".to_string(), |data| data.to_json()));
+            }
+            if !replay.is_identical() && !rescore {
+                println!("The replayed session differs from the recorded one!");
+                std::process::exit(1);
+            }
         }
         None => {}
     }
+    Ok(())
+}
+
+/// NamedPlayer is a player along with its name in the results
+type NamedPlayer = (Box<dyn Player>, String);
+
+/// choose_player starts the player chosen on the command line, along with its name, none to play with the keyboard
+fn choose_player(args: cli::PlayerArgs, seed: u64) -> Result<Option<NamedPlayer>, GameError> {
+    let timeout = Duration::from_secs(args.timeout);
+    if let Some(bot) = args.bot {
+        let kind: BotKind = bot.parse().map_err(GameError::Player)?;
+        return Ok(Some((Box::new(Bot::new(kind, seed)), kind.name().to_string())));
+    }
+    if let Some(command) = args.exec {
+        // the name of the program, without its directory nor its arguments
+        let program = command.split_whitespace().next().unwrap_or("command");
+        let program = Path::new(program).file_name().map_or(program.to_string(), |name| name.to_string_lossy().to_string());
+        return Ok(Some((Box::new(CommandPlayer::spawn(&command, Some(timeout), args.restarts)?), program)));
+    }
+    if let Some(base_url) = args.chat {
+        let model = args.model.expect("clap requires the model of the chat");
+        let config = ChatConfig {
+            base_url,
+            model: model.clone(),
+            temperature: args.temperature,
+            max_tokens: args.max_tokens,
+            system_prompt: args.system_prompt,
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            timeout,
+        };
+        return Ok(Some((Box::new(ChatPlayer::new(config)), model)));
+    }
+    Ok(None)
+}
+
+/// print_distribution prints the summary of the scores, then how many turns got each score
+fn print_distribution(name: &str, scores: &[usize]) {
+    if scores.is_empty() {
+        println!("No turn played");
+        return;
+    }
+    let summary = Summary::new(scores);
+    println!(
+        "{}: {} turns, mean score {:.1}, std {:.1}, 95% CI [{:.1}, {:.1}], min {}, max {}",
+        name, summary.turns, summary.mean, summary.std, summary.ci95_low, summary.ci95_high, summary.min, summary.max
+    );
+    let mut distinct = scores.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+    for score in distinct {
+        let count = scores.iter().filter(|&&s| s == score).count();
+        println!("{:>5} | {:<40} {}", score, "#".repeat((count * 40).div_ceil(scores.len())), count);
+    }
 }
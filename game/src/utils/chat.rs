@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
+use crate::logic::export::{ChatMessage, Role};
+use crate::logic::message::Message;
+use crate::utils::player::Player;
+use crate::utils::tcp::Data;
+
+/// ChatConfig is how to reach a chat model served by an OpenAI-compatible API, such as OpenAI or Ollama.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatConfig {
+    /// base URL of the API, to which `/chat/completions` is appended, such as `http://localhost:11434/v1`
+    pub base_url: String,
+    pub model: String,
+    pub temperature: f64,
+    /// maximum number of tokens of every answer
+    pub max_tokens: usize,
+    /// instructions sent before the messages of the game, if any
+    pub system_prompt: Option<String>,
+    /// key sent as a bearer token, if any
+    pub api_key: Option<String>,
+    /// how long a completion may take
+    pub timeout: Duration,
+}
+
+/// ChatPlayer is a chat model playing the game, as the research harness plays it.
+///
+/// Every line of the game is a message of the user in the conversation, and the model is asked to complete the
+/// conversation for every line requiring an answer. The conversation starts over with every turn, and the model is
+/// never asked to play again: it answers no, so that a client plays a single turn with it.
+pub struct ChatPlayer {
+    config: ChatConfig,
+    agent: ureq::Agent,
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    temperature: f64,
+    max_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChatMessage,
+}
+
+impl ChatPlayer {
+    pub fn new(config: ChatConfig) -> ChatPlayer {
+        let agent = ureq::AgentBuilder::new().timeout(config.timeout).build();
+        let mut player = ChatPlayer { config, agent, messages: vec![] };
+        player.start_over();
+        player
+    }
+
+    /// messages is the conversation of the current turn
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    fn start_over(&mut self) {
+        self.messages = self.config.system_prompt.iter()
+            .map(|prompt| ChatMessage { role: Role::System, content: prompt.clone() })
+            .collect();
+    }
+
+    /// complete asks the model for the next message of the conversation
+    fn complete(&self) -> Result<String, GameError> {
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+        let mut request = self.agent.post(&url);
+        if let Some(key) = &self.config.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+        let body = CompletionRequest {
+            model: &self.config.model,
+            messages: &self.messages,
+            temperature: self.config.temperature,
+            max_tokens: self.config.max_tokens,
+        };
+        let response: CompletionResponse = request
+            .send_json(body)
+            .map_err(|e| GameError::Player(format!("the chat model {} failed: {}", self.config.model, e)))?
+            .into_json()?;
+        match response.choices.into_iter().next() {
+            Some(choice) => Ok(choice.message.content),
+            None => Err(GameError::Player(format!("the chat model {} gave no answer", self.config.model))),
+        }
+    }
+}
+
+impl Player for ChatPlayer {
+    fn play(&mut self, data: &Data) -> Result<Option<String>, GameError> {
+        match data.message() {
+            Some(Message::Intro { .. }) => self.start_over(),
+            Some(Message::PlayAgain) => return Ok(Some("n".to_string())),
+            _ => {}
+        }
+        if data.content().is_empty() {
+            return Ok(None);
+        }
+        self.messages.push(ChatMessage { role: Role::User, content: data.content().to_string() });
+        if !data.require_input() {
+            return Ok(None);
+        }
+        let answer = self.complete()?;
+        self.messages.push(ChatMessage { role: Role::Assistant, content: answer.clone() });
+        Ok(Some(answer.trim().to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::logic::bench::bench;
+    use crate::logic::config::GameConfig;
+    use crate::logic::engine::GameMode;
+
+    /// mock serves chat completions answering the first position, and keeps the body of every request
+    fn mock() -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let base_url = format!("http://127.0.0.1:{}/v1", server.server_addr().to_ip().unwrap().port());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                assert_eq!(request.url(), "/v1/chat/completions");
+                let body: serde_json::Value = serde_json::from_reader(request.as_reader()).unwrap();
+                received.lock().unwrap().push(body);
+                let answer = r#"{"choices": [{"index": 0, "message": {"role": "assistant", "content": " 0\n"}}]}"#;
+                request.respond(tiny_http::Response::from_string(answer)).unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    #[test]
+    fn test_chat_player() {
+        let (base_url, requests) = mock();
+        let config = ChatConfig {
+            base_url,
+            model: "mock".to_string(),
+            temperature: 0.0,
+            max_tokens: 20,
+            system_prompt: Some("Answer with a number.".to_string()),
+            api_key: None,
+            timeout: Duration::from_secs(5),
+        };
+        let mut player = ChatPlayer::new(config);
+        let report = bench(None, Some(GameMode::Zero), GameConfig::default(), Some(3), &mut player, "mock".to_string(), 2).unwrap();
+        assert_eq!(report.scores.len(), 2);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2 * GameConfig::default().user_n);
+        assert_eq!(requests[0]["model"], "mock");
+        assert_eq!(requests[0]["max_tokens"], 20);
+        let first = requests[0]["messages"].as_array().unwrap();
+        assert_eq!(first[0]["role"], "system");
+        assert!(first[1]["content"].as_str().unwrap().contains("Game Begins!"));
+        // the conversation goes on with the answers of the model, and starts over with every turn
+        let second = requests[1]["messages"].as_array().unwrap();
+        assert!(second[..first.len()] == first[..]);
+        assert_eq!(second[first.len()], serde_json::json!({"role": "assistant", "content": " 0\n"}));
+        let last = requests.last().unwrap()["messages"].as_array().unwrap();
+        assert_eq!(last.iter().filter(|message| message["role"] == "system").count(), 1);
+        assert_eq!(player.messages().last().unwrap().role, Role::User);
+    }
+}
@@ -1,5 +1,8 @@
+pub mod record;
 pub mod shuffle;
 pub mod tcp;
+pub mod player;
+pub mod chat;
 
 
 /// to_ordinal converts a number to its ordinal representation.
@@ -0,0 +1,187 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::error::GameError;
+use crate::logic::bot::Bot;
+use crate::logic::message::Message;
+use crate::utils::tcp::Data;
+
+/// Player is anything that plays the game from the lines the game sends, whatever the game runs on.
+pub trait Player {
+    /// play shows a line of the game to the player and returns the answer of the player if the line requires one
+    fn play(&mut self, data: &Data) -> Result<Option<String>, GameError>;
+}
+
+impl Player for Bot {
+    fn play(&mut self, data: &Data) -> Result<Option<String>, GameError> {
+        let Some(message) = data.message() else {
+            return Err(GameError::Protocol("the game sent no structured message".to_string()));
+        };
+        self.observe(message);
+        Ok(data.require_input().then(|| self.answer(message)))
+    }
+}
+
+/// CommandPlayer is an external program playing the game: every line of the game is written to its standard input
+/// as a JSON line, and it writes the answer to every line requiring one to its standard output, on a line.
+///
+/// A program that crashes is started again and fed the lines of the turn so far, without requiring answers, before
+/// the pending line. A program that does not answer in time is started again the same way, and the line is
+/// answered with nothing, which counts as a wrong answer.
+pub struct CommandPlayer {
+    command: String,
+    /// how long the program may take to answer a line, forever if none
+    timeout: Option<Duration>,
+    /// how many more times the program may be started again
+    restarts: usize,
+    process: Process,
+    /// the lines of the current turn, sent again to the program after a restart
+    turn: Vec<Data>,
+}
+
+impl CommandPlayer {
+    /// spawn starts the command with the shell
+    pub fn spawn(command: &str, timeout: Option<Duration>, restarts: usize) -> Result<CommandPlayer, GameError> {
+        Ok(CommandPlayer { command: command.to_string(), timeout, restarts, process: Process::spawn(command)?, turn: vec![] })
+    }
+
+    /// restart starts the program again and sends it the lines of the turn so far
+    fn restart(&mut self, reason: &str) -> Result<(), GameError> {
+        if self.restarts == 0 {
+            return Err(GameError::Player(format!("the program `{}` {} and cannot be started again", self.command, reason)));
+        }
+        self.restarts -= 1;
+        eprintln!("The program `{}` {}, starting it again", self.command, reason);
+        self.process = Process::spawn(&self.command)?;
+        for data in &self.turn {
+            let mut replayed = Data::new(false, data.content().to_string());
+            if let Some(message) = data.message() {
+                replayed = replayed.with_message(message.clone());
+            }
+            // a program crashing right away is noticed with the pending line
+            if self.process.send(&replayed).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Player for CommandPlayer {
+    fn play(&mut self, data: &Data) -> Result<Option<String>, GameError> {
+        if matches!(data.message(), Some(Message::Intro { .. })) {
+            self.turn.clear();
+        }
+        loop {
+            match self.process.ask(data, self.timeout) {
+                Ok(answer) => {
+                    self.turn.push(data.clone());
+                    return Ok(answer);
+                }
+                Err(Failure::Crashed) => self.restart("crashed")?,
+                Err(Failure::TimedOut) => {
+                    self.turn.push(data.clone());
+                    self.restart("did not answer in time")?;
+                    return Ok(Some(String::new()));
+                }
+            }
+        }
+    }
+}
+
+/// Failure is why a program could not answer a line.
+enum Failure {
+    Crashed,
+    TimedOut,
+}
+
+/// Process is a running program, whose standard output is read line by line in the background, so that its
+/// answers can be awaited with a timeout.
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    answers: Receiver<String>,
+}
+
+impl Process {
+    fn spawn(command: &str) -> Result<Process, GameError> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("the standard input is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("the standard output is piped"));
+        let (sender, answers) = mpsc::channel();
+        // the thread ends when the program closes its standard output, or when the process is dropped
+        std::thread::spawn(move || {
+            for line in stdout.lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Process { child, stdin, answers })
+    }
+
+    fn send(&mut self, data: &Data) -> std::io::Result<()> {
+        writeln!(self.stdin, "{}", data.to_json())?;
+        self.stdin.flush()
+    }
+
+    /// ask sends the line to the program and waits for its answer if the line requires one
+    fn ask(&mut self, data: &Data, timeout: Option<Duration>) -> Result<Option<String>, Failure> {
+        self.send(data).map_err(|_| Failure::Crashed)?;
+        if !data.require_input() {
+            return Ok(None);
+        }
+        let answer = match timeout {
+            Some(timeout) => self.answers.recv_timeout(timeout).map_err(|e| match e {
+                RecvTimeoutError::Timeout => Failure::TimedOut,
+                RecvTimeoutError::Disconnected => Failure::Crashed,
+            })?,
+            None => self.answers.recv().map_err(|_| Failure::Crashed)?,
+        };
+        Ok(Some(answer.trim().to_string()))
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Data {
+        Data::new(true, "Where?\n".to_string()).with_message(Message::ProbeRequest { kind: crate::logic::message::ProbeKind::Reality, user_id: 0 })
+    }
+
+    #[test]
+    fn test_crashed_program_is_restarted() {
+        // answers the first line requiring one, then exits
+        let command = r#"while read line; do case "$line" in *'"require_input":true'*) echo 2; exit;; esac; done"#;
+        let mut player = CommandPlayer::spawn(command, None, 1).unwrap();
+        let intro = Data::new(false, "Game Begins!\n".to_string()).with_message(Message::Intro { n_users: 1, lockers: vec![vec![Some(0)]] });
+        assert_eq!(player.play(&intro).unwrap(), None);
+        assert_eq!(player.play(&request()).unwrap(), Some("2".to_string()));
+        assert_eq!(player.play(&request()).unwrap(), Some("2".to_string()));
+        assert_eq!(player.turn.len(), 3);
+        assert!(matches!(player.play(&request()), Err(GameError::Player(_))));
+    }
+
+    #[test]
+    fn test_slow_program_is_restarted() {
+        let mut player = CommandPlayer::spawn("cat > /dev/null", Some(Duration::from_millis(100)), 1).unwrap();
+        assert_eq!(player.play(&request()).unwrap(), Some(String::new()));
+        assert_eq!(player.restarts, 0);
+    }
+}
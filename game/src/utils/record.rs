@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::GameError;
+use crate::logic::config::GameConfig;
+use crate::logic::engine::{Game, GameMode};
+use crate::logic::message::PredictionRecord;
+use crate::utils::tcp::Data;
+
+/// Entry is a line of the transcript of a session.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    /// Everything needed to play the session again, always the first line
+    Session {
+        timestamp: u64,
+        session_id: u64,
+        server_version: String,
+        mode: GameMode,
+        seed: u64,
+        config: Box<GameConfig>,
+        /// capabilities accepted in the handshake, none for legacy clients
+        capabilities: Vec<String>,
+    },
+    /// A line sent to the player
+    Sent { timestamp: u64, data: Data },
+    /// A line received from the player, as is
+    Received { timestamp: u64, line: String },
+    /// The ground truth of a prediction, whether it was sent to the player or not
+    Prediction { timestamp: u64, record: PredictionRecord },
+    /// A snapshot pushed to the snapshots of a locker in the turn
+    Snapshot {
+        timestamp: u64,
+        turn: usize,
+        #[serde(default)]
+        locker_id: usize,
+        idx: usize,
+        locker: Vec<Option<usize>>,
+    },
+}
+
+/// Recorder writes the transcript of a session as JSON lines.
+pub struct Recorder {
+    file: BufWriter<File>,
+    turn: usize,
+    /// number of snapshots of each locker already recorded
+    snapshots_n: Vec<usize>,
+}
+
+impl Recorder {
+    /// create opens the transcript `session_<id>.jsonl` in the directory
+    pub fn create(dir: &Path, session_id: u64) -> Result<Recorder, GameError> {
+        let file = File::create(dir.join(format!("session_{}.jsonl", session_id)))?;
+        Ok(Recorder { file: BufWriter::new(file), turn: 0, snapshots_n: vec![] })
+    }
+
+    pub fn record(&mut self, entry: &Entry) -> Result<(), GameError> {
+        serde_json::to_writer(&mut self.file, entry)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// record_snapshots records the snapshots pushed by the game since the last call
+    pub fn record_snapshots(&mut self, game: &Game) -> Result<(), GameError> {
+        if game.turn() != self.turn {
+            self.turn = game.turn();
+            self.snapshots_n.clear();
+        }
+        self.snapshots_n.resize(game.snapshots().len(), 0);
+        for (locker_id, snapshots) in game.snapshots().iter().enumerate() {
+            for (idx, locker) in snapshots.iter().enumerate().skip(self.snapshots_n[locker_id]) {
+                let entry = Entry::Snapshot { timestamp: timestamp(), turn: self.turn, locker_id, idx, locker: locker.layout() };
+                self.record(&entry)?;
+            }
+            self.snapshots_n[locker_id] = snapshots.len();
+        }
+        Ok(())
+    }
+}
+
+/// timestamp is the number of milliseconds since the Unix epoch
+pub fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::message::Message;
+    use crate::logic::replay::replay;
+    use crate::logic::session::record_session;
+
+    #[test]
+    fn test_record_session() {
+        let dir = std::env::temp_dir().join(format!("game-record-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let turns = 3;
+        let config = GameConfig { user_n: 4, locker_n: 2, ..Default::default() };
+        let path = record_session(&dir, GameMode::Finite, config, 5, turns);
+
+        let entries: Vec<Entry> = std::fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert!(matches!(entries[0], Entry::Session { seed: 5, .. }));
+        // the turn of every line sent, and the snapshots of every locker in every turn
+        let mut turn = None;
+        let mut snapshots: Vec<Vec<Vec<Vec<Option<usize>>>>> = vec![vec![vec![]; 2]; turns];
+        for entry in &entries {
+            match entry {
+                Entry::Snapshot { turn: snapshot_turn, locker_id, idx, locker, .. } => {
+                    // every snapshot is written once, numbered from 0 again with every turn
+                    assert_eq!(*idx, snapshots[*snapshot_turn][*locker_id].len());
+                    snapshots[*snapshot_turn][*locker_id].push(locker.clone());
+                }
+                Entry::Sent { data, .. } => match data.message() {
+                    Some(Message::Intro { lockers, .. }) => {
+                        let next = turn.map_or(0, |turn| turn + 1);
+                        // the snapshots of a turn are written before its first line
+                        for (locker_id, layout) in lockers.iter().enumerate() {
+                            assert_eq!(&snapshots[next][locker_id][0], layout);
+                        }
+                        turn = Some(next);
+                    }
+                    Some(Message::Shuffle { locker_id, locker }) => assert!(snapshots[turn.unwrap()][*locker_id].contains(locker)),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        assert_eq!(turn, Some(turns - 1));
+
+        let replay = replay(&path).unwrap();
+        assert!(replay.is_identical());
+        assert_eq!(replay.scores.len(), turns);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
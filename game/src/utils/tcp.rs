@@ -5,14 +5,18 @@ use std::{
 };
 use std::fmt::Display;
 
+use crate::error::GameError;
+use crate::utils::player::Player;
+use crate::logic::message::{Hello, Message, Welcome, CAPABILITY_MESSAGES, PROTOCOL_VERSION};
+
 const SEPARATOR: u8 = 0x0a;
 
 // Server
-pub fn server<F>(port: usize, handler: F)
+pub fn server<F>(port: usize, handler: F) -> Result<(), GameError>
     where
-        F: Fn(TcpStream) + Send + Sync + 'static,
+        F: Fn(TcpStream) -> Result<(), GameError> + Send + Sync + 'static,
 {
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).expect("Failed to bind address");
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
     println!("Server listening on port {}", port);
     let handler = Arc::new(handler);
 
@@ -22,8 +26,11 @@ pub fn server<F>(port: usize, handler: F)
                 println!("New player connected!");
                 let handler = Arc::clone(&handler);
                 std::thread::spawn(move || {
-                    handler(stream);
-                    println!("Player disconnected!");
+                    match handler(stream) {
+                        Ok(()) => println!("Player disconnected!"),
+                        Err(GameError::Disconnected) => println!("Player disconnected in the middle of the game!"),
+                        Err(e) => eprintln!("Session failed: {}", e),
+                    }
                 });
             }
             Err(e) => {
@@ -31,46 +38,84 @@ pub fn server<F>(port: usize, handler: F)
             }
         }
     }
+    Ok(())
 }
 
 // Client
-pub fn client(port: usize) {
-    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).expect("Failed to connect to server");
+/// connect opens a session on the server with the handshake, announcing the structured messages
+pub fn connect(port: usize, seed: Option<u64>, client_name: String) -> Result<(TcpStream, Welcome), GameError> {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port))?;
+
+    let hello = Hello {
+        protocol_version: PROTOCOL_VERSION,
+        client_name,
+        capabilities: vec![CAPABILITY_MESSAGES.to_string()],
+        seed,
+    };
+    write_line(&mut stream, &serde_json::to_string(&hello)?)?;
+    let welcome = read_until_separator(&mut stream)?;
+    let welcome: Welcome = serde_json::from_slice(&welcome)
+        .map_err(|e| GameError::Protocol(format!("invalid welcome from the server: {}", e)))?;
+    if welcome.protocol_version != PROTOCOL_VERSION {
+        return Err(GameError::Protocol(format!(
+            "incompatible server {}: protocol version {}, expected {}",
+            welcome.server_version, welcome.protocol_version, PROTOCOL_VERSION
+        )));
+    }
+    println!("Connected to server {} in {:?} mode, session {}", welcome.server_version, welcome.mode, welcome.session_id);
+    Ok((stream, welcome))
+}
 
+/// client plays a session on the server with the player, or with the keyboard if none is given
+pub fn client(port: usize, seed: Option<u64>, mut player: Option<&mut dyn Player>) -> Result<(), GameError> {
+    let (mut stream, _) = connect(port, seed, format!("game-client/{}", env!("CARGO_PKG_VERSION")))?;
+
+    // the server closes the connection once the game is over
     loop {
-        let buffer = read_until_separator(&mut stream).unwrap_or_else(|_| {
-            println!("Connection closed");
-            std::process::exit(0);
-        });
+        let buffer = match read_until_separator(&mut stream) {
+            Ok(buffer) => buffer,
+            Err(GameError::Disconnected) => {
+                println!("Connection closed");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
         let response = String::from_utf8_lossy(&buffer).to_string();
         let response = response.trim();
-        let response = Data::from_json(&response);
-        if response.require_input() {
-            println!("{}", response.content());
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let data = Data::new(false, input.trim().to_string());
-            write_to_stream(&mut stream, data).unwrap();
-        } else if response.content().contains("Game Over!") {
-            println!("{}", response.content());
-            break;
-        } else {
-            println!("{}", response.content());
+        let response = Data::from_json(response)?;
+        if let Some(seed) = response.seed() {
+            println!("Session seed: {}", seed);
+        }
+        println!("{}", response.content());
+        match player.as_mut() {
+            Some(player) => {
+                if let Some(answer) = player.play(&response)? {
+                    println!("> {}", answer);
+                    write_to_stream(&mut stream, Data::new(false, answer))?;
+                }
+            }
+            None if response.require_input() => {
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                let data = Data::new(false, input.trim().to_string());
+                write_to_stream(&mut stream, data)?;
+            }
+            None => {}
         }
     }
 }
 
 /// read_until_separator reads data from the stream until a separator is found
-pub fn read_until_separator(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+pub fn read_until_separator(stream: &mut TcpStream) -> Result<Vec<u8>, GameError> {
     let mut content_buffer = Vec::new();
 
     // Read data into the buffer until the separator is found
     loop {
         let mut chunk = [0; 1]; // Buffer for reading chunks of data
-        let bytes_read = stream.read(&mut chunk).unwrap();
+        let bytes_read = stream.read(&mut chunk)?;
         if bytes_read == 0 {
             // return an error if the stream is closed
-            return Err(io::Error::new(io::ErrorKind::Other, "Stream closed"));
+            return Err(GameError::Disconnected);
         }
 
         // Check for the separator in the chunk and handle partial reads
@@ -85,20 +130,31 @@ pub fn read_until_separator(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
 }
 
 /// write_to_stream writes data to the stream and appends a separator at the end
-pub fn write_to_stream(stream: &mut TcpStream, data: Data) -> io::Result<()> {
-    let mut data = data.to_json().as_bytes().to_vec();
+pub fn write_to_stream(stream: &mut TcpStream, data: Data) -> Result<(), GameError> {
+    write_line(stream, &data.to_json())
+}
+
+/// write_line writes a line of JSON to the stream and appends a separator at the end
+pub fn write_line(stream: &mut TcpStream, json: &str) -> Result<(), GameError> {
+    let mut data = json.as_bytes().to_vec();
     data.push(SEPARATOR);
-    stream.write(&data).unwrap();
-    stream.flush().unwrap();
+    stream.write_all(&data)?;
+    stream.flush()?;
     Ok(())
 }
 
 
 /// Data to be sent over the network
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Data {
     require_input: bool,
     content: String,
+    /// seed of the session, only sent along with the first message of the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// structured counterpart of the content, only sent by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<Message>,
 }
 
 impl Display for Data {
@@ -112,10 +168,20 @@ impl Data {
         Self {
             require_input,
             content,
+            seed: None,
+            message: None,
         }
     }
-    pub fn from_json(json: &str) -> Self {
-        serde_json::from_str(json).unwrap()
+    pub fn with_message(mut self, message: Message) -> Self {
+        self.message = Some(message);
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    pub fn from_json(json: &str) -> Result<Self, GameError> {
+        Ok(serde_json::from_str(json)?)
     }
     pub fn to_json(&self) -> String {
         serde_json::to_string(self).unwrap()
@@ -126,4 +192,10 @@ impl Data {
     pub fn content(&self) -> &str {
         &self.content
     }
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+    pub fn message(&self) -> Option<&Message> {
+        self.message.as_ref()
+    }
 }
\ No newline at end of file
@@ -9,20 +9,22 @@ from dotenv import load_dotenv, find_dotenv
 
 from src.evaluations import Player
 from src.models.openai_model import OpenAIModel
+from src.utils.tcp.helper import handshake
 
 load_dotenv(find_dotenv())
 
 
 async def main():
     # =====para=======
-    mode = "finite"  # "zero" or "finite"
+    mode = "finite"  # "zero", "finite", "infinite" or "second-order"
     model = "phi3:3.8b-instruct"
     turns = 60
 
     # ======run======
-    port = 8080 if mode == "zero" else 8081
+    port = {"zero": 8080, "finite": 8081, "infinite": 8082, "second-order": 8083}[mode]
     client_socket = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
     client_socket.connect(('127.0.0.1', port))
+    welcome = handshake(client_socket, f"research/{model}")
     player = Player(model, client_socket)
     start_time = time.time()
     scores = await player.play(n_turns=turns)
@@ -37,6 +39,8 @@ async def main():
         os.makedirs("results")
     with open(f"results/{mode}_{model}_{turns}.txt", "w") as f:
         f.write(f"{mean_scores}\n")
+        f.write(f"server_version={welcome['server_version']} mode={welcome['mode']} "
+                f"session_id={welcome['session_id']} seed={welcome['seed']}\n")
 
 
 if __name__ == "__main__":
@@ -14,6 +14,7 @@ class Player:
 
     async def play(self, n_turns: int) -> [int]:
         scores = []
+        message_type = None
         for n_turn in range(n_turns):
             print("=====================================")
             print("Turn " + str(n_turn + 1) + " of " + str(n_turns) + "\n")
@@ -22,13 +23,18 @@ class Player:
                 try:
                     buffer = read_until_separator(self.client_socket)
                 except Exception as e:
+                    # a server with a turn limit closes the session after the last GameOver
+                    if message_type == "GameOver":
+                        print("Session over")
+                        return scores
                     print("Connection closed")
                     exit(0)
                 response = buffer.decode().strip()
                 response = json.loads(response)
                 response = Data.from_dict(response)
 
-                if response.require_input and "Game Over!" not in response.content:
+                message_type = response.message["type"]
+                if message_type in ("PredictionRequest", "ProbeRequest"):
                     print(response.content + "\n")
                     history = model.get_history()
                     message = {
@@ -52,29 +58,19 @@ class Player:
                         "content": prediction
                     })
                     write_to_stream(self.client_socket, prediction)
-                elif "Game Over!" in response.content:
-                    match = re.search(r"Final score:\s+(\d+)", response.content)
-                    if match:
-                        score = match.group(1)
-                    else:
-                        raise ValueError("No match")
+                elif message_type == "GameOver":
                     print(response.content + "\n")
-                    # self.client_socket.close()
-                    scores.append(int(score))
-                    if n_turn == n_turns - 1:
-                        response = Data.from_dict({
-                            "require_input": False,
-                            "content": "N"
-                        })
-                        write_to_stream(self.client_socket, response)
+                    scores.append(response.message["score"])
+                    if len(scores) == n_turns:
                         return scores
-                    else:
-                        response = Data.from_dict({
-                            "require_input": False,
-                            "content": "Y"
-                        })
-                        write_to_stream(self.client_socket, response)
-                        break
+                elif message_type == "PlayAgain":
+                    # the scores are returned with the GameOver of the last turn, another turn is always wanted here
+                    response = Data.from_dict({
+                        "require_input": False,
+                        "content": "Y"
+                    })
+                    write_to_stream(self.client_socket, response)
+                    break
                 else:
                     print(response.content + "\n")
                     message = {
@@ -2,9 +2,11 @@ import json
 
 
 class Data:
-    def __init__(self, require_input: bool, content: str):
+    def __init__(self, require_input: bool, content: str, message: dict = None):
         self.require_input = require_input
         self.content = content
+        # structured counterpart of the content, e.g. {"type": "GameOver", "correct": 3, "score": 60}
+        self.message = message
 
     def to_dict(self):
         return {
@@ -14,7 +16,7 @@ class Data:
 
     @staticmethod
     def from_dict(data: dict):
-        return Data(data["require_input"], data["content"])
+        return Data(data["require_input"], data["content"], data.get("message"))
 
 
 def read_until_separator(client_socket):
@@ -39,3 +41,23 @@ def write_to_stream(client_socket, data: Data):
     data = json.dumps(data).encode()
     data += bytes([0x0a])
     client_socket.sendall(data)
+
+
+PROTOCOL_VERSION = 1
+
+
+def handshake(client_socket, client_name: str, seed: int = None) -> dict:
+    """Send the Hello of the client, opting into structured messages, and return the Welcome of the server."""
+    hello = {
+        "type": "Hello",
+        "protocol_version": PROTOCOL_VERSION,
+        "client_name": client_name,
+        "capabilities": ["messages"],
+    }
+    if seed is not None:
+        hello["seed"] = seed
+    client_socket.sendall(json.dumps(hello).encode() + bytes([0x0a]))
+    welcome = json.loads(read_until_separator(client_socket).decode())
+    if welcome["protocol_version"] != PROTOCOL_VERSION:
+        raise Exception(f"Incompatible server {welcome['server_version']}: protocol version {welcome['protocol_version']}")
+    return welcome